@@ -0,0 +1,264 @@
+//! Asset registry, loaded once at startup
+
+use ggez::audio::SoundData;
+use ggez::filesystem;
+use ggez::graphics;
+use ggez::{Context, GameError, GameResult};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::audio::Music;
+use crate::level::{level_files, LevelDef};
+use crate::theme::Theme;
+
+// Sound effects
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum SoundId {
+    Block,
+    Paddle,
+}
+
+impl SoundId {
+    fn path(self) -> &'static str {
+        match self {
+            SoundId::Block => "/321585__waxxman__bip.mp3",
+            SoundId::Paddle => "/399196__spiceprogram__perc-bip.wav",
+        }
+    }
+}
+
+// Everything loaded from the resources directory
+// Levels and themes can be replaced while running, in development mode
+pub struct Assets {
+    pub font: graphics::Font,                    // font for all text
+    levels: RefCell<BTreeMap<String, LevelDef>>, // level layouts, by file name
+    themes: RefCell<HashMap<String, Theme>>,     // color themes, by file name
+    sounds: HashMap<SoundId, SoundData>,         // sound effects
+    music: HashMap<Music, SoundData>,            // music tracks that were found
+    textures: HashMap<String, graphics::Image>,  // images, by file name
+}
+
+impl Assets {
+    // level layouts, in file name order
+    pub fn levels(&self) -> Vec<LevelDef> {
+        self.levels.borrow().values().cloned().collect()
+    }
+
+    pub fn set_level(&self, name: &str, def: LevelDef) {
+        self.levels.borrow_mut().insert(name.to_owned(), def);
+    }
+
+    // theme with the given name, or the default one
+    pub fn theme(&self, name: &str) -> Theme {
+        self.themes.borrow().get(name).cloned().unwrap_or_default()
+    }
+
+    pub fn set_theme(&self, name: &str, theme: Theme) {
+        self.themes.borrow_mut().insert(name.to_owned(), theme);
+    }
+
+    pub fn sound(&self, id: SoundId) -> &SoundData {
+        &self.sounds[&id]
+    }
+
+    // music is optional
+    pub fn music(&self, music: Music) -> Option<&SoundData> {
+        self.music.get(&music.track())
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&graphics::Image> {
+        self.textures.get(name)
+    }
+}
+
+// Something left to load
+enum Step {
+    Sound(SoundId),
+    Music(Music, PathBuf),
+    Texture(PathBuf),
+    Theme(PathBuf),
+    Level(PathBuf),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::Sound(id) => write!(f, "{}", id.path()),
+            Step::Music(_, path)
+            | Step::Texture(path)
+            | Step::Theme(path)
+            | Step::Level(path) => {
+                write!(f, "{}", path.display())
+            }
+        }
+    }
+}
+
+// Loads assets one at a time, so progress can be shown
+pub struct Loader {
+    pub font: graphics::Font,    // font, loaded first to show progress
+    levels_dir: Option<PathBuf>, // directory to read levels from, instead of the resources
+    steps: Vec<Step>,            // left to load, last one first
+    total: usize,                // total number of steps
+    loading: Option<Assets>,     // assets loaded so far
+    loaded: Option<Rc<Assets>>,  // all assets, once done
+}
+
+impl Loader {
+    // load the font, and find out what else to load
+    // levels come from the given directory if any, or from the resources
+    pub fn new(ctx: &mut Context, levels_dir: Option<PathBuf>) -> GameResult<Self> {
+        let font = graphics::Font::new(ctx, FONT).map_err(|e| load_error(Path::new(FONT), e))?;
+
+        let mut steps = Vec::new();
+        for id in &[SoundId::Block, SoundId::Paddle] {
+            steps.push(Step::Sound(*id));
+        }
+        for music in Music::all() {
+            let path = music.path();
+            if filesystem::is_file(ctx, &path) {
+                steps.push(Step::Music(music, path));
+            }
+        }
+        for path in list_files(ctx, TEXTURES_DIR, "png")? {
+            steps.push(Step::Texture(path));
+        }
+        for path in list_files(ctx, THEMES_DIR, "txt")? {
+            steps.push(Step::Theme(path));
+        }
+        let levels = match &levels_dir {
+            Some(dir) => level_files(dir).map_err(|e| load_error(dir, e))?,
+            None => list_files(ctx, LEVELS_DIR, "txt")?,
+        };
+        if levels.is_empty() {
+            let dir = levels_dir.as_deref().unwrap_or_else(|| Path::new(LEVELS_DIR));
+            return Err(GameError::ResourceNotFound(
+                format!("No level file in {}", dir.display()),
+                Vec::new(),
+            ));
+        }
+        for path in levels {
+            steps.push(Step::Level(path));
+        }
+        steps.reverse();
+
+        Ok(Self {
+            font,
+            levels_dir,
+            total: steps.len(),
+            steps,
+            loading: Some(Assets {
+                font,
+                levels: RefCell::new(BTreeMap::new()),
+                themes: RefCell::new(HashMap::new()),
+                sounds: HashMap::new(),
+                music: HashMap::new(),
+                textures: HashMap::new(),
+            }),
+            loaded: None,
+        })
+    }
+
+    // progress between 0 and 1
+    pub fn progress(&self) -> f32 {
+        1.0 - self.steps.len() as f32 / self.total as f32
+    }
+
+    // name of the file being loaded
+    pub fn current(&self) -> Option<String> {
+        self.steps.last().map(|s| s.to_string())
+    }
+
+    // all assets, once everything is loaded
+    pub fn assets(&self) -> Option<Rc<Assets>> {
+        self.loaded.clone()
+    }
+
+    // load the next asset, the error names the file that failed
+    pub fn step(&mut self, ctx: &mut Context) -> GameResult {
+        let step = match self.steps.pop() {
+            Some(step) => step,
+            None => return Ok(()),
+        };
+        let assets = self.loading.as_mut().unwrap();
+        match &step {
+            Step::Sound(id) => {
+                let data = SoundData::new(ctx, id.path())
+                    .map_err(|e| load_error(Path::new(id.path()), e))?;
+                assets.sounds.insert(*id, data);
+            }
+            Step::Music(music, path) => {
+                let data = SoundData::new(ctx, path).map_err(|e| load_error(path, e))?;
+                assets.music.insert(*music, data);
+            }
+            Step::Texture(path) => {
+                let image = graphics::Image::new(ctx, path).map_err(|e| load_error(path, e))?;
+                assets.textures.insert(file_name(path), image);
+            }
+            Step::Theme(path) => {
+                let text = read_text(ctx, path)?;
+                let theme = Theme::parse(&text).map_err(|e| load_error(path, e))?;
+                assets.set_theme(&file_name(path), theme);
+            }
+            Step::Level(path) => {
+                let text = if self.levels_dir.is_some() {
+                    fs::read_to_string(path).map_err(|e| load_error(path, e))?
+                } else {
+                    read_text(ctx, path)?
+                };
+                let def = LevelDef::parse(&text).map_err(|e| load_error(path, e))?;
+                assets.set_level(&file_name(path), def);
+            }
+        }
+        if self.steps.is_empty() {
+            self.loaded = self.loading.take().map(Rc::new);
+        }
+        Ok(())
+    }
+}
+
+// read a whole text file
+pub fn read_text(ctx: &mut Context, path: &Path) -> GameResult<String> {
+    let mut text = String::new();
+    filesystem::open(ctx, path)
+        .and_then(|mut f| f.read_to_string(&mut text).map_err(GameError::from))
+        .map_err(|e| load_error(path, e))?;
+    Ok(text)
+}
+
+// files with the given extension in a resource directory, sorted by name
+fn list_files(ctx: &mut Context, dir: &str, extension: &str) -> GameResult<Vec<PathBuf>> {
+    if !filesystem::is_dir(ctx, dir) {
+        return Ok(Vec::new());
+    }
+    let mut files: Vec<PathBuf> = filesystem::read_dir(ctx, dir)?
+        .filter(|p| p.extension() == Some(OsStr::new(extension)))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// file name without directory and extension
+pub fn file_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn load_error<E: fmt::Display>(path: &Path, e: E) -> GameError {
+    GameError::ResourceLoadError(format!("Cannot load {}: {}", path.display(), e))
+}
+
+// Font for all text
+const FONT: &str = "/PixelEmulator-xq08.ttf";
+
+// Directories where assets are found
+pub const LEVELS_DIR: &str = "/levels";
+pub const THEMES_DIR: &str = "/themes";
+const TEXTURES_DIR: &str = "/textures";
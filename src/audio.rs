@@ -0,0 +1,228 @@
+//! Sound effects and music, shared by all states
+
+use ggez::audio::{SoundData, SoundSource, Source};
+use ggez::{Context, GameResult};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::assets::{Assets, SoundId};
+
+// Sound effects the states can ask for
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Sfx {
+    Block(u32), // block broken, with the current combo
+    Paddle,     // ball bounced on the paddle
+    Laser,      // paddle fired laser shots
+    PowerUp,    // paddle caught a power-up
+}
+
+// Background music tracks
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Music {
+    Title,
+    Level(u32), // track for the given level index
+    Complete,
+    GameOver,
+}
+
+impl Music {
+    // all distinct tracks
+    pub fn all() -> Vec<Music> {
+        let mut all = vec![Music::Title, Music::Complete, Music::GameOver];
+        all.extend((0..LEVEL_TRACKS).map(Music::Level));
+        all
+    }
+
+    // the distinct track used, since levels share tracks
+    pub fn track(self) -> Music {
+        match self {
+            Music::Level(ix) => Music::Level(ix % LEVEL_TRACKS),
+            _ => self,
+        }
+    }
+
+    pub fn path(self) -> PathBuf {
+        match self.track() {
+            Music::Title => PathBuf::from("/music/title.ogg"),
+            Music::Level(ix) => PathBuf::from(format!("/music/level{}.ogg", ix + 1)),
+            Music::Complete => PathBuf::from("/music/complete.ogg"),
+            Music::GameOver => PathBuf::from("/music/gameover.ogg"),
+        }
+    }
+}
+
+// Several sources for the same sound, so rapid plays overlap instead of cutting each other
+struct Pool {
+    sources: Vec<Source>,
+    next: usize, // source to use on next play
+}
+
+impl Pool {
+    fn new(ctx: &mut Context, data: &SoundData) -> GameResult<Self> {
+        let mut sources = Vec::new();
+        for _ in 0..POOL_SIZE {
+            sources.push(Source::from_data(ctx, data.clone())?);
+        }
+        Ok(Self { sources, next: 0 })
+    }
+
+    fn play(&mut self, volume: f32, pitch: f32) -> GameResult {
+        let ix = self.next;
+        self.next = (ix + 1) % self.sources.len();
+        let source = &mut self.sources[ix];
+        source.set_volume(volume);
+        source.set_pitch(pitch);
+        source.play()
+    }
+}
+
+// Audio manager: everything keeps working, silently, if sounds cannot be played
+pub struct AudioManager {
+    enabled: bool,                          // false if there is no audio device
+    block: Option<Pool>,                    // sound when hitting a block
+    paddle: Option<Pool>,                   // sound when hitting the paddle
+    tracks: HashMap<Music, Source>,         // music tracks that could be loaded
+    current: Option<Music>,                 // music playing
+    pub music_volume: f32,                  // between 0 and 1
+    pub sfx_volume: f32,                    // between 0 and 1
+    pub muted: bool,
+}
+
+impl AudioManager {
+    // silent until the assets are loaded
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            block: None,
+            paddle: None,
+            tracks: HashMap::new(),
+            current: None,
+            music_volume: 0.5,
+            sfx_volume: 1.0,
+            muted: false,
+        }
+    }
+
+    // prepare sources for the loaded sounds, if we have an audio device
+    pub fn load(&mut self, ctx: &mut Context, assets: &Assets) {
+        if !self.enabled {
+            return;
+        }
+        self.block = load_pool(ctx, assets, SoundId::Block);
+        self.paddle = load_pool(ctx, assets, SoundId::Paddle);
+        for music in Music::all() {
+            if let Some(source) = load_music(ctx, assets, music) {
+                self.tracks.insert(music, source);
+            }
+        }
+    }
+
+    pub fn play_sfx(&mut self, sfx: Sfx) {
+        if self.muted {
+            return;
+        }
+        let (pool, pitch) = match sfx {
+            // each combo step sounds a bit higher
+            Sfx::Block(combo) => {
+                let step = combo.clamp(1, MAX_PITCH_STEPS) - 1;
+                (&mut self.block, 1.0 + step as f32 * PITCH_STEP)
+            }
+            Sfx::Paddle => (&mut self.paddle, 1.0),
+            // no sound files of their own, the others played higher or lower
+            Sfx::Laser => (&mut self.paddle, LASER_PITCH),
+            Sfx::PowerUp => (&mut self.block, POWERUP_PITCH),
+        };
+        if let Some(p) = pool {
+            p.play(self.sfx_volume, pitch)
+                .unwrap_or_else(|e| println!("Cannot play sound:{}", e));
+        }
+    }
+
+    // switch to the given music, if it's not already playing
+    pub fn play_music(&mut self, music: Music) {
+        let music = music.track();
+        if !self.enabled || self.current == Some(music) {
+            return;
+        }
+        if let Some(source) = self.current.and_then(|m| self.tracks.get_mut(&m)) {
+            source.stop();
+        }
+        self.current = Some(music);
+        let volume = self.effective_music_volume();
+        if let Some(source) = self.tracks.get_mut(&music) {
+            source.set_volume(volume);
+            source
+                .play()
+                .unwrap_or_else(|e| println!("Cannot play music:{}", e));
+        }
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+        self.update_music_volume();
+    }
+
+    // change music volume by the given delta
+    pub fn change_music_volume(&mut self, delta: f32) {
+        self.music_volume = (self.music_volume + delta).clamp(0.0, 1.0);
+        self.update_music_volume();
+    }
+
+    // change sound effects volume by the given delta
+    pub fn change_sfx_volume(&mut self, delta: f32) {
+        self.sfx_volume = (self.sfx_volume + delta).clamp(0.0, 1.0);
+    }
+
+    fn effective_music_volume(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.music_volume
+        }
+    }
+
+    // apply volume to the music playing
+    fn update_music_volume(&mut self) {
+        let volume = self.effective_music_volume();
+        if let Some(source) = self.current.and_then(|m| self.tracks.get_mut(&m)) {
+            source.set_volume(volume);
+        }
+    }
+}
+
+// create a sound effect pool, reporting failure
+fn load_pool(ctx: &mut Context, assets: &Assets, id: SoundId) -> Option<Pool> {
+    Pool::new(ctx, assets.sound(id))
+        .map_err(|e| println!("Cannot load sound {:?}:{}", id, e))
+        .ok()
+}
+
+// create a looping music source, if the track was found
+fn load_music(ctx: &mut Context, assets: &Assets, music: Music) -> Option<Source> {
+    let data = assets.music(music)?;
+    match Source::from_data(ctx, data.clone()) {
+        Ok(mut source) => {
+            source.set_repeat(true);
+            Some(source)
+        }
+        Err(e) => {
+            println!("Cannot load music {:?}:{}", music, e);
+            None
+        }
+    }
+}
+
+// Number of sources per sound effect
+const POOL_SIZE: usize = 4;
+
+// Pitch increase per combo step
+const PITCH_STEP: f32 = 0.05;
+// Combo steps after which the pitch stops increasing
+const MAX_PITCH_STEPS: u32 = 10;
+
+// Pitch of the sounds reused for laser shots and power-ups
+const LASER_PITCH: f32 = 2.0;
+const POWERUP_PITCH: f32 = 0.7;
+
+// Number of different level tracks
+const LEVEL_TRACKS: u32 = 3;
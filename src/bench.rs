@@ -0,0 +1,64 @@
+//! Headless benchmark: play games without a window and report how fast the simulation runs
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Instant;
+
+use rust_blocks::controller::{Autopilot, PaddleController};
+use rust_blocks::level::{next_level, LevelDef};
+use rust_blocks::sim::*;
+
+use crate::cli::Options;
+
+// Play the given number of games, starting at the level given in the options
+pub fn run(options: &Options, defs: &[LevelDef], games: u32) {
+    let mut rng = StdRng::seed_from_u64(options.seed.unwrap_or_default());
+    let start = Instant::now();
+    let mut ticks: u64 = 0;
+    let mut levels = 0;
+    let mut warps = 0;
+    let mut total_score: u64 = 0;
+    for _ in 0..games {
+        let mut sim = Sim::new(next_level(defs, options.level), 0, LIVES, rng.gen());
+        let mut autopilot = Autopilot::new(rng.gen());
+        let mut game_ticks = 0;
+        while sim.mode != PlayMode::Lost && game_ticks < MAX_TICKS_PER_GAME {
+            if sim.mode == PlayMode::Won {
+                // a warp skips levels without clearing the one played
+                if sim.stats.skipped > 0 {
+                    warps += 1;
+                } else {
+                    levels += 1;
+                }
+                let level = next_level(defs, sim.level.index + 1 + sim.stats.skipped);
+                sim = Sim::new(level, sim.score, sim.lives, rng.gen());
+            }
+            for input in autopilot.inputs(&sim) {
+                sim.input(input);
+            }
+            sim.tick();
+            sim.take_events();
+            game_ticks += 1;
+        }
+        ticks += game_ticks as u64;
+        total_score += sim.score as u64;
+    }
+    let secs = start.elapsed().as_secs_f64();
+    println!(
+        "{} games, {} levels cleared, {} warps, average score {:.1}",
+        games,
+        levels,
+        warps,
+        total_score as f64 / games.max(1) as f64
+    );
+    println!(
+        "{} ticks in {:.3}s: {:.0} ticks per second ({:.0}x real time)",
+        ticks,
+        secs,
+        ticks as f64 / secs,
+        ticks as f64 / secs / TICKS_PER_SECOND as f64
+    );
+}
+
+// Games are cut short after ten minutes of play
+const MAX_TICKS_PER_GAME: u32 = TICKS_PER_SECOND * 60 * 10;
@@ -0,0 +1,235 @@
+//! Batch simulation for balancing: the autopilot plays every level many times,
+//! without a window, and statistics are printed as CSV or JSON
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::path::PathBuf;
+use std::process;
+use structopt::StructOpt;
+
+use rust_blocks::controller::{Autopilot, PaddleController};
+use rust_blocks::level::{next_level, read_levels, Level};
+use rust_blocks::sim::*;
+
+// Output formats
+#[derive(Clone, Copy, Debug)]
+enum Format {
+    Csv,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format '{}', expected csv or json", s)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "simulate", about = "Let the computer play every level and report statistics")]
+struct Options {
+    /// Games played on each level
+    #[structopt(long, default_value = "1000")]
+    games: u32,
+
+    /// Seed for the first game, each game gets its own
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+
+    /// How many times to go through all levels, to see how the speed increase plays
+    #[structopt(long, default_value = "1")]
+    laps: u32,
+
+    /// Directory to read level files from
+    #[structopt(long, parse(from_os_str))]
+    levels: Option<PathBuf>,
+
+    /// Output format: csv or json
+    #[structopt(long, default_value = "csv")]
+    format: Format,
+
+    /// Longest game, in minutes of play
+    #[structopt(long, default_value = "10")]
+    max_minutes: u32,
+}
+
+// Statistics for one level
+struct Report {
+    index: u32,       // level number
+    name: String,     // level name
+    speed: f32,       // ball speed
+    games: u32,       // games played
+    cleared: u32,     // games where all blocks were broken
+    warped: u32,      // games left through a warp exit
+    clear_ticks: u64, // total play time of cleared games
+    play_ticks: u64,  // total play time of all games
+    balls_lost: u32,  // balls lost over all games
+    stuck_loops: u32, // times the ball bounced on walls only for too long
+    scores: Vec<u32>, // score of each game, sorted
+}
+
+impl Report {
+    fn new(level: &Level) -> Self {
+        Self {
+            index: level.index,
+            name: level.name.clone(),
+            speed: level.ball_speed,
+            games: 0,
+            cleared: 0,
+            warped: 0,
+            clear_ticks: 0,
+            play_ticks: 0,
+            balls_lost: 0,
+            stuck_loops: 0,
+            scores: Vec::new(),
+        }
+    }
+
+    fn avg_clear_secs(&self) -> f64 {
+        if self.cleared == 0 {
+            return 0.0;
+        }
+        self.clear_ticks as f64 / self.cleared as f64 / TICKS_PER_SECOND as f64
+    }
+
+    fn balls_lost_per_minute(&self) -> f64 {
+        let minutes = self.play_ticks as f64 / TICKS_PER_SECOND as f64 / 60.0;
+        if minutes == 0.0 {
+            return 0.0;
+        }
+        self.balls_lost as f64 / minutes
+    }
+
+    fn per_game(&self, count: u32) -> f64 {
+        count as f64 / self.games.max(1) as f64
+    }
+
+    // score at the given fraction of the sorted scores
+    fn score_at(&self, fraction: f64) -> u32 {
+        if self.scores.is_empty() {
+            return 0;
+        }
+        let ix = ((self.scores.len() - 1) as f64 * fraction).round() as usize;
+        self.scores[ix]
+    }
+}
+
+// play a single level until it is won, lost or takes too long
+fn play(level: Level, seed: u64, max_ticks: u32, report: &mut Report) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut sim = Sim::new(level, 0, LIVES, rng.gen());
+    let mut autopilot = Autopilot::new(rng.gen());
+    let mut stuck = false;
+    while !sim.finished() && sim.ticks < max_ticks {
+        for input in autopilot.inputs(&sim) {
+            sim.input(input);
+        }
+        sim.tick();
+        sim.take_events();
+        // count each loop once
+        if sim.idle_bounces >= STUCK_BOUNCES && !stuck {
+            report.stuck_loops += 1;
+        }
+        stuck = sim.idle_bounces >= STUCK_BOUNCES;
+    }
+    report.games += 1;
+    report.play_ticks += sim.ticks as u64;
+    report.balls_lost += sim.stats.lives_lost;
+    if sim.mode == PlayMode::Won && sim.stats.skipped > 0 {
+        report.warped += 1;
+    } else if sim.mode == PlayMode::Won {
+        report.cleared += 1;
+        report.clear_ticks += sim.ticks as u64;
+    }
+    report.scores.push(sim.score);
+}
+
+fn print_csv(reports: &[Report]) {
+    println!("level,name,speed,games,cleared,warped,avg_clear_secs,balls_lost_per_game,balls_lost_per_minute,stuck_loops_per_game,score_min,score_p25,score_median,score_p75,score_max");
+    for r in reports {
+        println!(
+            "{},\"{}\",{},{},{},{},{:.1},{:.3},{:.3},{:.3},{},{},{},{},{}",
+            r.index,
+            r.name.replace('"', "\"\""),
+            r.speed,
+            r.games,
+            r.cleared,
+            r.warped,
+            r.avg_clear_secs(),
+            r.per_game(r.balls_lost),
+            r.balls_lost_per_minute(),
+            r.per_game(r.stuck_loops),
+            r.score_at(0.0),
+            r.score_at(0.25),
+            r.score_at(0.5),
+            r.score_at(0.75),
+            r.score_at(1.0)
+        );
+    }
+}
+
+fn print_json(reports: &[Report]) {
+    println!("[");
+    for (ix, r) in reports.iter().enumerate() {
+        let separator = if ix + 1 < reports.len() { "," } else { "" };
+        println!(
+            "  {{\"level\": {}, \"name\": {:?}, \"speed\": {}, \"games\": {}, \"cleared\": {}, \"warped\": {}, \
+             \"avg_clear_secs\": {:.1}, \"balls_lost_per_game\": {:.3}, \
+             \"balls_lost_per_minute\": {:.3}, \"stuck_loops_per_game\": {:.3}, \
+             \"score\": {{\"min\": {}, \"p25\": {}, \"median\": {}, \"p75\": {}, \"max\": {}}}}}{}",
+            r.index,
+            r.name,
+            r.speed,
+            r.games,
+            r.cleared,
+            r.warped,
+            r.avg_clear_secs(),
+            r.per_game(r.balls_lost),
+            r.balls_lost_per_minute(),
+            r.per_game(r.stuck_loops),
+            r.score_at(0.0),
+            r.score_at(0.25),
+            r.score_at(0.5),
+            r.score_at(0.75),
+            r.score_at(1.0),
+            separator
+        );
+    }
+    println!("]");
+}
+
+fn main() {
+    let options = Options::from_args();
+    let dir = options
+        .levels
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/levels"));
+    let defs = match read_levels(&dir) {
+        Ok(defs) => defs,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let max_ticks = options.max_minutes * 60 * TICKS_PER_SECOND;
+    let mut seed = options.seed;
+    let mut reports = Vec::new();
+    for index in 0..defs.len() as u32 * options.laps {
+        let mut report = Report::new(&next_level(&defs, index));
+        for _ in 0..options.games {
+            play(next_level(&defs, index), seed, max_ticks, &mut report);
+            seed = seed.wrapping_add(1);
+        }
+        report.scores.sort_unstable();
+        reports.push(report);
+    }
+    match options.format {
+        Format::Csv => print_csv(&reports),
+        Format::Json => print_json(&reports),
+    }
+}
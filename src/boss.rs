@@ -0,0 +1,169 @@
+//! Boss fights: a large enemy replacing the blocks, with weak spots and attacks getting
+//! fiercer as it loses health
+
+use ggez::graphics;
+use rand::Rng;
+
+use super::*;
+use crate::sim::Bounce;
+
+// Projectile fired by the boss, that the paddle must dodge
+#[derive(Clone, Copy, Debug)]
+pub struct Shot {
+    pub pos: (f32, f32),   // center
+    pub speed: (f32, f32), // speed vector
+}
+
+// The boss
+#[derive(Clone, Debug)]
+pub struct Boss {
+    pub rect: graphics::Rect, // body
+    pub health: u32,          // hit points left
+    pub max_health: u32,      // hit points when full
+    pub shots: Vec<Shot>,     // projectiles in flight
+    direction: f32,           // moving right (1) or left (-1)
+    cooldown: u32,            // ticks before the next attack
+}
+
+impl Boss {
+    pub fn new(health: u32) -> Self {
+        Self {
+            rect: graphics::Rect::new((WIDTH - BOSS_WIDTH) / 2.0, BOSS_Y, BOSS_WIDTH, BOSS_HEIGHT),
+            health,
+            max_health: health,
+            shots: Vec::new(),
+            direction: 1.0,
+            cooldown: FIRE_INTERVALS[0],
+        }
+    }
+
+    pub fn defeated(&self) -> bool {
+        self.health == 0
+    }
+
+    // phase of the fight, from 0 to 2, changing at each third of the health lost
+    pub fn phase(&self) -> usize {
+        let lost = (self.max_health - self.health) * 3 / self.max_health.max(1);
+        (lost as usize).min(2)
+    }
+
+    // parts of the body that take more damage
+    pub fn weak_spots(&self) -> [graphics::Rect; 2] {
+        let y = self.rect.y + self.rect.h - WEAK_SPOT_HEIGHT;
+        [
+            graphics::Rect::new(
+                self.rect.x + self.rect.w / 4.0 - WEAK_SPOT_WIDTH / 2.0,
+                y,
+                WEAK_SPOT_WIDTH,
+                WEAK_SPOT_HEIGHT,
+            ),
+            graphics::Rect::new(
+                self.rect.x + self.rect.w * 3.0 / 4.0 - WEAK_SPOT_WIDTH / 2.0,
+                y,
+                WEAK_SPOT_WIDTH,
+                WEAK_SPOT_HEIGHT,
+            ),
+        ]
+    }
+
+    // move, attack the paddle at the given position and move the shots
+    pub fn tick<R: Rng>(&mut self, paddle: f32, rng: &mut R) {
+        let phase = self.phase();
+        self.rect.x += self.direction * MOVE_SPEEDS[phase];
+        if self.rect.x < BOSS_MARGIN || self.rect.x + self.rect.w > WIDTH - BOSS_MARGIN {
+            self.direction = -self.direction;
+            self.rect.x = self
+                .rect
+                .x
+                .clamp(BOSS_MARGIN, WIDTH - BOSS_MARGIN - self.rect.w);
+        }
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+        } else {
+            self.attack(phase, paddle, rng);
+            self.cooldown = FIRE_INTERVALS[phase];
+        }
+        for s in self.shots.iter_mut() {
+            s.pos.0 += s.speed.0;
+            s.pos.1 += s.speed.1;
+        }
+        self.shots.retain(|s| s.pos.1 - SHOT_RADIUS < 420.0);
+    }
+
+    // fire: straight down at first, then at the paddle, then a spread around the paddle
+    fn attack<R: Rng>(&mut self, phase: usize, paddle: f32, rng: &mut R) {
+        let from = (self.rect.x + self.rect.w / 2.0, self.rect.y + self.rect.h);
+        let aim = |x: f32| {
+            let (dx, dy) = (x - from.0, 400.0 - from.1);
+            let distance = (dx * dx + dy * dy).sqrt();
+            (SHOT_SPEED * dx / distance, SHOT_SPEED * dy / distance)
+        };
+        let targets = match phase {
+            0 => vec![from.0 + rng.gen_range(-SPREAD, SPREAD)],
+            1 => vec![paddle],
+            _ => vec![paddle - SPREAD, paddle, paddle + SPREAD],
+        };
+        for x in targets {
+            self.shots.push(Shot {
+                pos: from,
+                speed: aim(x),
+            });
+        }
+    }
+
+    // ball at the given position and speed hitting the boss: side to bounce on and damage done
+    pub fn hit(
+        &mut self,
+        ball_rect: &graphics::Rect,
+        ball: (f32, f32),
+        speed: (f32, f32),
+    ) -> Option<(Bounce, u32)> {
+        if self.defeated() || !self.rect.overlaps(ball_rect) {
+            return None;
+        }
+        let (bx, by) = ball;
+        let r = self.rect;
+        // only bounce when the ball is going towards the boss, not when already leaving
+        let bounce = if speed.1 < 0.0 && by > r.y + r.h {
+            Bounce::Bottom
+        } else if speed.1 > 0.0 && by < r.y {
+            Bounce::Top
+        } else if speed.0 < 0.0 && bx > r.x + r.w {
+            Bounce::Right
+        } else if speed.0 > 0.0 && bx < r.x {
+            Bounce::Left
+        } else {
+            return None;
+        };
+        let damage = if self.weak_spots().iter().any(|w| w.overlaps(ball_rect)) {
+            WEAK_SPOT_DAMAGE
+        } else {
+            1
+        };
+        self.health = self.health.saturating_sub(damage);
+        Some((bounce, damage))
+    }
+}
+
+pub const SHOT_RADIUS: f32 = 5.0;
+
+const BOSS_WIDTH: f32 = 240.0;
+const BOSS_HEIGHT: f32 = 80.0;
+// Top of the boss
+const BOSS_Y: f32 = 40.0;
+// Closest the boss gets to the walls
+const BOSS_MARGIN: f32 = 20.0;
+
+const WEAK_SPOT_WIDTH: f32 = 40.0;
+const WEAK_SPOT_HEIGHT: f32 = 16.0;
+// Damage done by hitting a weak spot
+const WEAK_SPOT_DAMAGE: u32 = 3;
+
+// Horizontal speed in each phase
+const MOVE_SPEEDS: [f32; 3] = [1.0, 1.5, 2.5];
+// Ticks between two attacks in each phase
+const FIRE_INTERVALS: [u32; 3] = [120, 90, 60];
+
+const SHOT_SPEED: f32 = 3.0;
+// Horizontal distance between shots in a spread
+const SPREAD: f32 = 60.0;
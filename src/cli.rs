@@ -0,0 +1,104 @@
+//! Command line options
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+// Window size
+#[derive(Clone, Copy, Debug)]
+pub struct Resolution {
+    pub width: f32,
+    pub height: f32,
+}
+
+// parse WIDTHxHEIGHT
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid resolution '{}', expected WIDTHxHEIGHT", s);
+        let mut parts = s.split('x');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(w), Some(h), None) => {
+                let width: u32 = w.parse().map_err(|_| error())?;
+                let height: u32 = h.parse().map_err(|_| error())?;
+                if width == 0 || height == 0 {
+                    return Err(error());
+                }
+                Ok(Resolution {
+                    width: width as f32,
+                    height: height as f32,
+                })
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+// Options given on the command line
+#[derive(StructOpt, Clone, Debug)]
+#[structopt(name = "blocks", about = "Break all the blocks!")]
+pub struct Options {
+    /// Level to start at, the first level being 0
+    #[structopt(long, default_value = "0")]
+    pub level: u32,
+
+    /// Seed for random numbers, to play the same game again
+    #[structopt(long)]
+    pub seed: Option<u64>,
+
+    /// Play full screen
+    #[structopt(long)]
+    pub fullscreen: bool,
+
+    /// Window size, as WIDTHxHEIGHT
+    #[structopt(long)]
+    pub resolution: Option<Resolution>,
+
+    /// Start with sound muted
+    #[structopt(long)]
+    pub mute: bool,
+
+    /// Play back a replay file instead of playing
+    #[structopt(long, parse(from_os_str))]
+    pub replay: Option<PathBuf>,
+
+    /// Record the last level played to a replay file
+    #[structopt(long, parse(from_os_str))]
+    pub record: Option<PathBuf>,
+
+    /// Play the given number of games without a window, and report how fast it went
+    #[structopt(long)]
+    pub benchmark: Option<u32>,
+
+    /// Directory to read level files from, instead of the resources
+    #[structopt(long, parse(from_os_str))]
+    pub levels: Option<PathBuf>,
+
+    /// Let the computer play
+    #[structopt(long)]
+    pub autopilot: bool,
+
+    /// Show where the ball will go when aiming the launch
+    #[structopt(long)]
+    pub preview: bool,
+
+    /// File where the best time attack run is saved
+    #[structopt(long, parse(from_os_str), default_value = "best_times.txt")]
+    pub best_times: PathBuf,
+
+    /// Show the debug overlay
+    #[structopt(long)]
+    pub debug: bool,
+
+    /// Reload levels and themes when their files change (also set by BLOCKS_DEV)
+    #[structopt(long)]
+    pub dev: bool,
+}
+
+impl Options {
+    // seed for the random numbers of the given level, so each level plays differently
+    pub fn level_seed(&self, index: u32) -> u64 {
+        self.seed.unwrap_or_default().wrapping_add(index as u64)
+    }
+}
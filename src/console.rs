@@ -0,0 +1,170 @@
+//! Developer console, to cheat while testing levels
+
+use ggez::event;
+use ggez::graphics;
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+
+use super::*;
+
+// A console command
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Level(u32),    // go to the given level
+    Clear,         // break all blocks
+    Speed(f32),    // change the ball speed
+    Spawn(String), // spawn a power-up of the given kind
+    Lives(u32),    // set the number of lives
+    God,           // toggle the ball bouncing at the bottom instead of falling
+    Seed(u64),     // reseed the random numbers
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["level", n] => Ok(Command::Level(parse_arg(n)?)),
+            ["clear"] => Ok(Command::Clear),
+            ["speed", f] => Ok(Command::Speed(parse_arg(f)?)),
+            ["spawn", "powerup", kind] => Ok(Command::Spawn((*kind).to_owned())),
+            ["lives", n] => Ok(Command::Lives(parse_arg(n)?)),
+            ["god"] => Ok(Command::God),
+            ["seed", n] => Ok(Command::Seed(parse_arg(n)?)),
+            _ => Err(format!("unknown command '{}', {}", line, HELP)),
+        }
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: &str) -> Result<T, String> {
+    arg.parse().map_err(|_| format!("invalid value '{}'", arg))
+}
+
+// Something console commands can be run on
+pub trait Commands {
+    // run the command, returning a message for the console
+    fn run(&mut self, command: &Command) -> Result<String, String>;
+}
+
+// Drop-down console, over the state running the commands
+pub struct ConsoleState {
+    input: String,          // line being typed
+    output: Vec<String>,    // previous commands and their results
+    commands: Vec<Command>, // commands to run
+}
+
+impl ConsoleState {
+    pub fn new() -> Self {
+        Self {
+            input: String::new(),
+            output: vec![HELP.to_owned()],
+            commands: Vec::new(),
+        }
+    }
+
+    fn print(&mut self, line: String) {
+        self.output.push(line);
+        if self.output.len() > OUTPUT_LINES {
+            self.output.remove(0);
+        }
+    }
+}
+
+impl event::EventHandler for ConsoleState {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mb = &mut graphics::MeshBuilder::new();
+        mb.rectangle(
+            graphics::DrawMode::Fill(graphics::FillOptions::default()),
+            graphics::Rect::new(0.0, 0.0, WIDTH, HEIGHT / 3.0),
+            BACKGROUND,
+        );
+        let m = &mb.build(ctx)?;
+        graphics::draw(ctx, m, graphics::DrawParam::new())?;
+        let font = graphics::Font::default();
+        let mut y = 5.0;
+        for line in self.output.iter() {
+            let text = graphics::Text::new((line.as_str(), font, 16.0));
+            graphics::draw(ctx, &text, (na::Point2::new(5.0, y), LIGHTGRAY))?;
+            y += 18.0;
+        }
+        let prompt = graphics::Text::new((format!("> {}_", self.input), font, 16.0));
+        graphics::draw(
+            ctx,
+            &prompt,
+            (na::Point2::new(5.0, HEIGHT / 3.0 - 22.0), graphics::WHITE),
+        )?;
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: event::KeyCode,
+        _keymod: event::KeyMods,
+        _repeat: bool,
+    ) {
+        match keycode {
+            event::KeyCode::Back => {
+                self.input.pop();
+            }
+            event::KeyCode::Return => {
+                let line = std::mem::take(&mut self.input);
+                if line.trim().is_empty() {
+                    return;
+                }
+                self.print(format!("> {}", line));
+                match Command::parse(&line) {
+                    Ok(command) => self.commands.push(command),
+                    Err(e) => self.print(e),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        // the key opening the console is not part of the command
+        if !character.is_control() && character != '`' && character != '~' {
+            self.input.push(character);
+        }
+    }
+}
+
+impl InnerState for ConsoleState {
+    fn transition(&self, keycode: event::KeyCode) -> Transition {
+        if keycode == event::KeyCode::Escape || keycode == event::KeyCode::Grave {
+            return Transition::Pop;
+        }
+        Transition::None
+    }
+
+    fn take_commands(&mut self) -> Vec<Command> {
+        std::mem::take(&mut self.commands)
+    }
+
+    fn command_result(&mut self, result: Result<String, String>) {
+        match result {
+            Ok(message) => self.print(message),
+            Err(e) => self.print(format!("error: {}", e)),
+        }
+    }
+
+    fn takes_text(&self) -> bool {
+        true
+    }
+}
+
+const HELP: &str = "commands: level <n>, clear, speed <f>, spawn powerup <kind>, lives <n>, god, seed <n>";
+
+// Lines of output kept on screen
+const OUTPUT_LINES: usize = 6;
+
+const BACKGROUND: graphics::Color = graphics::Color {
+    r: 0.0,
+    g: 0.0,
+    b: 0.0,
+    a: 0.8,
+};
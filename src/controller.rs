@@ -0,0 +1,200 @@
+//! Paddle controllers: the player, a replay, or the computer
+
+use ggez::event;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::*;
+use crate::replay::Replay;
+use crate::sim::*;
+
+// Decides how the paddle moves
+pub trait PaddleController {
+    // inputs to apply before the next tick
+    fn inputs(&mut self, sim: &Sim) -> Vec<Input>;
+
+    // key pressed by the player, ignored by controllers that do not listen to the keyboard
+    fn key_down(&mut self, _keycode: event::KeyCode, _repeat: bool) {}
+
+    // mouse moved to the given position on the playfield
+    fn mouse_motion(&mut self, _x: f32, _y: f32) {}
+}
+
+// The player, with the keyboard, and the mouse to aim
+#[derive(Default)]
+pub struct Keyboard {
+    pending: Vec<Input>,         // keys pressed since the last tick
+    turn: f32,                   // aim change asked with the keys since the last tick
+    pointer: Option<(f32, f32)>, // mouse position, if it moved since the last tick
+}
+
+impl PaddleController for Keyboard {
+    fn inputs(&mut self, sim: &Sim) -> Vec<Input> {
+        let mut inputs = std::mem::take(&mut self.pending);
+        let turn = std::mem::take(&mut self.turn);
+        let pointer = self.pointer.take();
+        if sim.mode != PlayMode::Pending {
+            return inputs;
+        }
+        // aim at the mouse, if it is above the ball
+        if let Some((x, y)) = pointer {
+            let (bx, by) = sim.ball;
+            if y < by {
+                inputs.push(Input::Aim((x - bx).atan2(by - y)));
+            }
+        }
+        if turn != 0.0 {
+            inputs.push(Input::Aim(sim.aim + turn));
+        }
+        inputs
+    }
+
+    fn key_down(&mut self, keycode: event::KeyCode, repeat: bool) {
+        let input = match keycode {
+            event::KeyCode::Space => Input::Launch,
+            event::KeyCode::Left => Input::Move(Direction::Left, repeat),
+            event::KeyCode::Right => Input::Move(Direction::Right, repeat),
+            event::KeyCode::A => {
+                self.turn -= AIM_STEP;
+                return;
+            }
+            event::KeyCode::D => {
+                self.turn += AIM_STEP;
+                return;
+            }
+            _ => return,
+        };
+        self.pending.push(input);
+    }
+
+    fn mouse_motion(&mut self, x: f32, y: f32) {
+        self.pointer = Some((x, y));
+    }
+}
+
+// Inputs recorded earlier
+impl PaddleController for Replay {
+    fn inputs(&mut self, sim: &Sim) -> Vec<Input> {
+        self.take(sim.ticks)
+    }
+}
+
+// The computer, moving the paddle where the ball will land
+pub struct Autopilot {
+    offset: f32,  // where on the paddle to catch the ball, to vary bounces
+    waiting: u32, // ticks waited before launching the ball
+    rng: StdRng,  // random offsets
+}
+
+impl Autopilot {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            offset: 0.0,
+            waiting: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl PaddleController for Autopilot {
+    fn inputs(&mut self, sim: &Sim) -> Vec<Input> {
+        match sim.mode {
+            PlayMode::Pending => {
+                self.waiting += 1;
+                if self.waiting < LAUNCH_DELAY {
+                    return Vec::new();
+                }
+                self.waiting = 0;
+                return vec![Input::Launch];
+            }
+            PlayMode::Running => (),
+            _ => return Vec::new(),
+        }
+        // let go of a held ball at once
+        if sim.caught.is_some() {
+            return vec![Input::Launch];
+        }
+        // new catch point after each paddle bounce
+        if sim.ball_speed.1 < 0.0 && sim.ball.1 > PADDLE_Y - 2.0 * BALL_RADIUS {
+            self.offset = self.rng.gen_range(-MAX_OFFSET, MAX_OFFSET);
+        }
+        let target = landing_x(sim) - self.offset;
+        // never push against a wall, that would go through an open warp exit
+        let mut inputs = if target < sim.paddle - PADDLE_SPEED / 2.0 && sim.paddle > LIMIT_LEFT {
+            vec![Input::Move(Direction::Left, false)]
+        } else if target > sim.paddle + PADDLE_SPEED / 2.0 && sim.paddle < LIMIT_RIGHT {
+            vec![Input::Move(Direction::Right, false)]
+        } else {
+            Vec::new()
+        };
+        // fire whenever possible
+        if sim.laser_ticks > 0 {
+            inputs.push(Input::Launch);
+        }
+        inputs
+    }
+}
+
+// where the ball center will cross the paddle line, bouncing on the walls and the top
+// but ignoring blocks in the way
+pub fn landing_x(sim: &Sim) -> f32 {
+    let (bx, by) = sim.ball;
+    let (sx, sy) = sim.ball_speed;
+    if sy == 0.0 {
+        return bx;
+    }
+    // vertical distance to travel, going up to the top first if needed
+    let distance = if sy > 0.0 {
+        PADDLE_Y - by
+    } else {
+        (by - BALL_RADIUS) + (PADDLE_Y - BALL_RADIUS)
+    };
+    let x = bx + sx * distance.max(0.0) / sy.abs();
+    reflect(x, BALL_RADIUS, WIDTH - BALL_RADIUS)
+}
+
+// fold a position into [low, high], as if bouncing on both ends
+fn reflect(x: f32, low: f32, high: f32) -> f32 {
+    let span = high - low;
+    let mut d = (x - low).rem_euclid(2.0 * span);
+    if d > span {
+        d = 2.0 * span - d;
+    }
+    low + d
+}
+
+// Height of the ball center when it touches the paddle
+const PADDLE_Y: f32 = 400.0 - BALL_RADIUS;
+
+// Aim change for each press of the aiming keys, in radians
+const AIM_STEP: f32 = 0.05;
+
+// Ticks the autopilot waits before launching the ball
+const LAUNCH_DELAY: u32 = 30;
+
+// Largest distance from the paddle center where the autopilot catches the ball
+const MAX_OFFSET: f32 = PADDLE_WIDTH / 3.0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{next_level, LevelDef};
+
+    #[test]
+    fn space_releases_caught_ball() {
+        let def = LevelDef::parse("name Test\nblocks\n#\n").unwrap();
+        let mut sim = Sim::new(next_level(&[def], 0), 0, LIVES, 0);
+        sim.input(Input::Launch);
+        sim.caught = Some((0.0, 100));
+        // space must reach the keyboard instead of pausing
+        assert!(sim.launch_in_play());
+        let mut keyboard = Keyboard::default();
+        keyboard.key_down(event::KeyCode::Space, false);
+        for input in keyboard.inputs(&sim) {
+            sim.input(input);
+        }
+        sim.tick();
+        assert!(sim.caught.is_none());
+        assert!(sim.ball_speed.1 < 0.0);
+    }
+}
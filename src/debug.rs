@@ -0,0 +1,162 @@
+//! Debug overlay: frame timing, state stack and collision details
+
+use ggez::graphics;
+use ggez::nalgebra as na;
+use ggez::timer;
+use ggez::{Context, GameResult};
+use std::time::Duration;
+
+use super::*;
+use crate::sim::*;
+
+// Shown over the game, toggled with F3
+pub struct DebugOverlay {
+    pub visible: bool,
+    tick_time: Duration, // time spent in the last tick
+}
+
+impl DebugOverlay {
+    pub fn new(visible: bool) -> Self {
+        Self {
+            visible,
+            tick_time: Duration::from_secs(0),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn set_tick_time(&mut self, tick_time: Duration) {
+        self.tick_time = tick_time;
+    }
+
+    // timing and state stack at the top, then whatever the current state wants to show
+    pub fn draw(&self, ctx: &mut Context, states: &[Box<dyn InnerState>]) -> GameResult {
+        if !self.visible {
+            return Ok(());
+        }
+        if let Some(state) = states.last() {
+            state.draw_debug(ctx)?;
+        }
+        let names: Vec<&str> = states
+            .iter()
+            .map(|s| s.name().rsplit("::").next().unwrap_or_default())
+            .collect();
+        let lines = vec![
+            format!(
+                "FPS {:.0}  frame {:.1}ms  tick {:.2}ms  frames {}",
+                timer::fps(ctx),
+                millis(timer::average_delta(ctx)),
+                millis(self.tick_time),
+                timer::ticks(ctx)
+            ),
+            format!("States: {}", names.join(" > ")),
+        ];
+        draw_lines(ctx, &lines, 5.0)
+    }
+}
+
+// collision details of a level being played
+pub fn draw_sim(ctx: &mut Context, sim: &Sim) -> GameResult {
+    let trace = &sim.trace;
+    let mb = &mut graphics::MeshBuilder::new();
+    let stroke = || graphics::DrawMode::Stroke(graphics::StrokeOptions::default());
+    mb.rectangle(stroke(), sim.paddle_zone(), ZONE);
+    for rect in trace.tested.iter() {
+        mb.rectangle(stroke(), *rect, TESTED);
+    }
+    for rect in trace.hit.iter() {
+        mb.rectangle(stroke(), *rect, HIT);
+    }
+    if sim.mode == PlayMode::Running {
+        mb.rectangle(stroke(), trace.ball_rect, BALL);
+        // where the ball will be in a few ticks
+        let from = na::Point2::new(sim.ball.0, sim.ball.1);
+        let to = na::Point2::new(
+            sim.ball.0 + sim.ball_speed.0 * VELOCITY_TICKS,
+            sim.ball.1 + sim.ball_speed.1 * VELOCITY_TICKS,
+        );
+        mb.line(&[from, to], 2.0, BALL)?;
+    }
+    let m = mb.build(ctx)?;
+    graphics::draw(ctx, &m, graphics::DrawParam::new())?;
+
+    let lines = vec![
+        format!(
+            "ball ({:.1}, {:.1})  speed ({:.2}, {:.2})  paddle {:.1}",
+            sim.ball.0, sim.ball.1, sim.ball_speed.0, sim.ball_speed.1, sim.paddle
+        ),
+        format!(
+            "{:?}  tick {}  tested {}  last bounce {:?}  combo {}  idle bounces {}",
+            sim.mode,
+            sim.ticks,
+            trace.tested.len(),
+            trace.bounce,
+            sim.combo,
+            sim.idle_bounces
+        ),
+    ];
+    draw_lines(ctx, &lines, 45.0)
+}
+
+// lines of text on a dark background
+fn draw_lines(ctx: &mut Context, lines: &[String], y: f32) -> GameResult {
+    let text = graphics::Text::new((lines.join("\n"), graphics::Font::default(), 14.0));
+    let (w, h) = text.dimensions(ctx);
+    let mb = &mut graphics::MeshBuilder::new();
+    mb.rectangle(
+        graphics::DrawMode::Fill(graphics::FillOptions::default()),
+        graphics::Rect::new(0.0, y - 3.0, w as f32 + 10.0, h as f32 + 6.0),
+        BACKGROUND,
+    );
+    let m = mb.build(ctx)?;
+    graphics::draw(ctx, &m, graphics::DrawParam::new())?;
+    graphics::draw(ctx, &text, (na::Point2::new(5.0, y), graphics::WHITE))
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+// How many ticks ahead the velocity vector goes
+const VELOCITY_TICKS: f32 = 10.0;
+
+const BACKGROUND: graphics::Color = graphics::Color {
+    r: 0.0,
+    g: 0.0,
+    b: 0.0,
+    a: 0.6,
+};
+
+// Ball approximation and velocity
+const BALL: graphics::Color = graphics::Color {
+    r: 1.0,
+    g: 0.8,
+    b: 0.0,
+    a: 1.0,
+};
+
+// Blocks tested against the ball
+const TESTED: graphics::Color = graphics::Color {
+    r: 0.0,
+    g: 0.4,
+    b: 1.0,
+    a: 1.0,
+};
+
+// Blocks hit by the last bounce
+const HIT: graphics::Color = graphics::Color {
+    r: 1.0,
+    g: 0.0,
+    b: 1.0,
+    a: 1.0,
+};
+
+// Where the paddle catches the ball
+const ZONE: graphics::Color = graphics::Color {
+    r: 0.0,
+    g: 0.8,
+    b: 0.2,
+    a: 1.0,
+};
@@ -0,0 +1,94 @@
+//! Enemies drifting down the playfield, deflecting the ball and dying when hit
+
+use rand::Rng;
+use std::str::FromStr;
+
+use super::*;
+
+// How an enemy moves
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Behaviour {
+    Drift,  // straight down and sideways, bouncing on the walls
+    Zigzag, // down, weaving left and right
+    Chase,  // down, towards the paddle
+}
+
+impl FromStr for Behaviour {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drift" => Ok(Behaviour::Drift),
+            "zigzag" => Ok(Behaviour::Zigzag),
+            "chase" => Ok(Behaviour::Chase),
+            _ => Err(format!("unknown enemy '{}', expected drift, zigzag or chase", s)),
+        }
+    }
+}
+
+// Enemies sent by a level
+#[derive(Clone, Copy, Debug)]
+pub struct EnemySpawn {
+    pub max: u32,             // most enemies on screen at the same time
+    pub every: f32,           // seconds between two enemies
+    pub behaviour: Behaviour, // how they move
+}
+
+// A single enemy
+#[derive(Clone, Copy, Debug)]
+pub struct Enemy {
+    pub pos: (f32, f32),      // center
+    pub speed: (f32, f32),    // speed vector
+    pub behaviour: Behaviour, // how it moves
+    age: u32,                 // ticks since it appeared
+}
+
+impl Enemy {
+    // new enemy coming in from one of the spawn points at the top
+    pub fn new<R: Rng>(behaviour: Behaviour, rng: &mut R) -> Self {
+        let x = SPAWN_POINTS[rng.gen_range(0, SPAWN_POINTS.len())];
+        Self {
+            pos: (x, -ENEMY_RADIUS),
+            speed: (rng.gen_range(-ENEMY_SPEED, ENEMY_SPEED), ENEMY_SPEED / 2.0),
+            behaviour,
+            age: 0,
+        }
+    }
+
+    // move for one tick, chasers going towards the given paddle position
+    pub fn tick(&mut self, paddle: f32) {
+        self.age += 1;
+        match self.behaviour {
+            Behaviour::Drift => (),
+            Behaviour::Zigzag => {
+                self.speed.0 = ENEMY_SPEED * (self.age as f32 * ZIGZAG_RATE).cos();
+            }
+            Behaviour::Chase => {
+                self.speed.0 = ((paddle - self.pos.0) / 20.0).clamp(-ENEMY_SPEED, ENEMY_SPEED);
+            }
+        }
+        self.pos.0 += self.speed.0;
+        self.pos.1 += self.speed.1;
+        // bounce on the walls
+        if self.pos.0 < ENEMY_RADIUS || self.pos.0 > WIDTH - ENEMY_RADIUS {
+            self.speed.0 = -self.speed.0;
+            self.pos.0 = self.pos.0.clamp(ENEMY_RADIUS, WIDTH - ENEMY_RADIUS);
+        }
+    }
+
+    // has the enemy left the screen at the bottom?
+    pub fn gone(&self) -> bool {
+        self.pos.1 - ENEMY_RADIUS > 420.0
+    }
+}
+
+pub const ENEMY_RADIUS: f32 = 12.0;
+
+// Where enemies come in at the top of the screen
+const SPAWN_POINTS: [f32; 2] = [WIDTH / 4.0, WIDTH * 3.0 / 4.0];
+
+// Fastest sideways speed of an enemy, twice its falling speed
+const ENEMY_SPEED: f32 = 1.0;
+
+// How fast zigzagging enemies weave, in radians per tick
+const ZIGZAG_RATE: f32 = 0.05;
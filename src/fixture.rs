@@ -0,0 +1,151 @@
+//! Fixtures: level elements that are never destroyed but change the ball,
+//! bumpers, one-way walls, portals and speed zones
+
+use ggez::graphics;
+use ggez::nalgebra as na;
+
+use crate::sim::BALL_RADIUS;
+
+// A fixture, in pixel
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Fixture {
+    Bumper { center: (f32, f32), radius: f32 },      // pushes the ball away, faster
+    OneWay { rect: graphics::Rect, up: bool },       // wall the ball only goes through upwards or downwards
+    Portal { ends: [(f32, f32); 2] },                // a ball going in one end comes out of the other
+    SpeedZone { rect: graphics::Rect, factor: f32 }, // the ball moves faster or slower inside
+}
+
+// What a fixture does to the ball touching it
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Touch {
+    Bounce((f32, f32)),   // new ball speed
+    Teleport((f32, f32)), // new ball position
+}
+
+impl Fixture {
+    // horizontal one-way wall from its left end
+    pub fn one_way(x: f32, y: f32, width: f32, up: bool) -> Self {
+        let rect = graphics::Rect::new(x, y - ONE_WAY_THICKNESS / 2.0, width, ONE_WAY_THICKNESS);
+        Fixture::OneWay { rect, up }
+    }
+
+    // effect on the ball at the given position and speed, if any;
+    // bumpers send the ball at the given base speed, made faster
+    pub fn touch(&self, ball: (f32, f32), speed: (f32, f32), base_speed: f32) -> Option<Touch> {
+        match *self {
+            Fixture::Bumper { center, radius } => {
+                let (dx, dy) = (ball.0 - center.0, ball.1 - center.1);
+                let dist = (dx * dx + dy * dy).sqrt();
+                if dist > radius + BALL_RADIUS || dist == 0.0 {
+                    return None;
+                }
+                let normal = (dx / dist, dy / dist);
+                let dot = speed.0 * normal.0 + speed.1 * normal.1;
+                if dot >= 0.0 {
+                    return None;
+                }
+                // mirror the direction on the bumper, at more than the base speed
+                let (sx, sy) = (speed.0 - 2.0 * dot * normal.0, speed.1 - 2.0 * dot * normal.1);
+                let scale = base_speed * BUMPER_BOOST / (sx * sx + sy * sy).sqrt();
+                Some(Touch::Bounce((sx * scale, sy * scale)))
+            }
+            Fixture::OneWay { rect, up } => {
+                let blocked = if up { speed.1 > 0.0 } else { speed.1 < 0.0 };
+                let inside = ball.0 >= rect.x
+                    && ball.0 <= rect.x + rect.w
+                    && ball.1 + BALL_RADIUS >= rect.y
+                    && ball.1 - BALL_RADIUS <= rect.y + rect.h;
+                if blocked && inside {
+                    Some(Touch::Bounce((speed.0, -speed.1)))
+                } else {
+                    None
+                }
+            }
+            Fixture::Portal { ends } => {
+                // only when going in, not when coming out of the other end
+                let previous = (ball.0 - speed.0, ball.1 - speed.1);
+                for (ix, end) in ends.iter().enumerate() {
+                    if in_portal(ball, *end) && !in_portal(previous, *end) {
+                        return Some(Touch::Teleport(ends[1 - ix]));
+                    }
+                }
+                None
+            }
+            Fixture::SpeedZone { .. } => None,
+        }
+    }
+
+    // how much faster the ball moves at the given position
+    pub fn speed_factor(&self, ball: (f32, f32)) -> f32 {
+        match *self {
+            Fixture::SpeedZone { rect, factor } if rect.contains(na::Point2::new(ball.0, ball.1)) => factor,
+            _ => 1.0,
+        }
+    }
+}
+
+// is the ball center inside the portal end?
+fn in_portal(ball: (f32, f32), end: (f32, f32)) -> bool {
+    let (dx, dy) = (ball.0 - end.0, ball.1 - end.1);
+    dx * dx + dy * dy <= PORTAL_RADIUS * PORTAL_RADIUS
+}
+
+// Ball speed after hitting a bumper, compared to the level speed
+const BUMPER_BOOST: f32 = 1.5;
+
+pub const ONE_WAY_THICKNESS: f32 = 6.0;
+
+pub const PORTAL_RADIUS: f32 = 15.0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bumper_pushes_ball_away_faster() {
+        let bumper = Fixture::Bumper {
+            center: (200.0, 200.0),
+            radius: 20.0,
+        };
+        match bumper.touch((200.0, 225.0), (0.0, -5.0), 5.0) {
+            Some(Touch::Bounce((sx, sy))) => {
+                assert!(sx.abs() < 1e-4);
+                assert!((sy - 5.0 * BUMPER_BOOST).abs() < 1e-4);
+            }
+            touch => panic!("unexpected {:?}", touch),
+        }
+        // already going away
+        assert_eq!(bumper.touch((200.0, 225.0), (0.0, 5.0), 5.0), None);
+    }
+
+    #[test]
+    fn one_way_passes_one_way_and_bounces_the_other() {
+        let wall = Fixture::one_way(100.0, 200.0, 100.0, true);
+        assert_eq!(wall.touch((150.0, 200.0), (1.0, -5.0), 5.0), None);
+        assert_eq!(wall.touch((150.0, 200.0), (1.0, 5.0), 5.0), Some(Touch::Bounce((1.0, -5.0))));
+        // beside the wall
+        assert_eq!(wall.touch((250.0, 200.0), (1.0, 5.0), 5.0), None);
+    }
+
+    #[test]
+    fn portal_moves_ball_to_other_end() {
+        let portal = Fixture::Portal {
+            ends: [(100.0, 100.0), (500.0, 300.0)],
+        };
+        // only the position changes, so the speed is kept
+        assert_eq!(portal.touch((100.0, 112.0), (0.0, -5.0), 5.0), Some(Touch::Teleport((500.0, 300.0))));
+        // coming out of the other end does not send it back
+        assert_eq!(portal.touch((500.0, 295.0), (0.0, -5.0), 5.0), None);
+    }
+
+    #[test]
+    fn speed_zone_changes_speed_inside_only() {
+        let zone = Fixture::SpeedZone {
+            rect: graphics::Rect::new(0.0, 280.0, 800.0, 40.0),
+            factor: 0.7,
+        };
+        assert_eq!(zone.speed_factor((400.0, 300.0)), 0.7);
+        assert_eq!(zone.speed_factor((400.0, 100.0)), 1.0);
+        assert_eq!(zone.touch((400.0, 300.0), (0.0, 5.0), 5.0), None);
+    }
+}
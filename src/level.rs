@@ -2,18 +2,183 @@
 
 use ggez::graphics;
 use std::collections::HashSet;
+use std::f32::consts::TAU;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
 use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use super::*;
+use crate::enemy::EnemySpawn;
+use crate::fixture::Fixture;
+use crate::sim::Direction;
+use crate::WIDTH;
+
+// Kind of block, deciding its look and its value
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum BlockKind {
+    Light,
+    Dark,
+    Hidden, // not drawn, and not needed to clear the level
+}
+
+impl BlockKind {
+    // kinds alternate on the grid like a checker board
+    fn checkered(i: i32, j: i32) -> Self {
+        if i % 2 == j % 2 {
+            BlockKind::Light
+        } else {
+            BlockKind::Dark
+        }
+    }
+}
+
+// Corner of a grid cell
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+// Shape of a block in its grid cell
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Shape {
+    Rect,             // the whole cell
+    Half,             // the bottom half of the cell
+    Triangle(Corner), // right triangle filling the given corner, sloped on the other side
+    Circle,           // circle as high as the cell, in its middle
+}
+
+// How a block moves during the level, from its place in the grid
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Motion {
+    Still,
+    Slide { distance: f32, period: f32 },       // left and right, in pixels, one round trip per period in seconds
+    Rotate { center: (f32, f32), period: f32 }, // around a point, one turn per period in seconds
+    Descend { speed: f32 },                     // down, in pixels per second, until DESCEND_LIMIT
+}
 
 // A single block
 #[derive(Clone, Copy, Debug)]
 pub struct Block {
     pub i: i32,                  // X position in grid
     pub j: i32,                  // Y position in grid
-    pub rect: graphics::Rect,    // position in pixel
-    pub fill: graphics::Color,   // fill color
-    pub stroke: graphics::Color, // stroke color
+    pub kind: BlockKind,      // kind of block
+    pub shape: Shape,         // shape inside rect
+    pub rect: graphics::Rect, // position in pixel
+    pub motion: Motion,       // how the block moves
+}
+
+impl Block {
+    // block at the given grid position
+    fn new(i: i32, j: i32, kind: BlockKind, shape: Shape) -> Self {
+        Block {
+            i,
+            j,
+            kind,
+            shape,
+            rect: Block::grid_rect(i, j),
+            motion: Motion::Still,
+        }
+    }
+
+    // position in pixel of a place in the grid
+    fn grid_rect(i: i32, j: i32) -> graphics::Rect {
+        let minx = (i as f32) * BLOCK_WIDTH;
+        let miny = (j as f32) * BLOCK_HEIGHT;
+        graphics::Rect::new(minx, miny, BLOCK_WIDTH, BLOCK_HEIGHT)
+    }
+
+    // position in pixel after the given time in seconds
+    pub fn position(&self, time: f32) -> graphics::Rect {
+        let mut rect = Block::grid_rect(self.i, self.j);
+        match self.motion {
+            Motion::Still => (),
+            Motion::Slide { distance, period } => {
+                rect.x += distance * (time / period * TAU).sin();
+            }
+            Motion::Rotate { center, period } => {
+                let (sin, cos) = (time / period * TAU).sin_cos();
+                let (dx, dy) = (rect.x + rect.w / 2.0 - center.0, rect.y + rect.h / 2.0 - center.1);
+                rect.x = center.0 + dx * cos - dy * sin - rect.w / 2.0;
+                rect.y = center.1 + dx * sin + dy * cos - rect.h / 2.0;
+            }
+            Motion::Descend { speed } => {
+                rect.y += (speed * time).min(DESCEND_LIMIT - rect.y - rect.h).max(0.0);
+            }
+        }
+        rect
+    }
+
+    // corners of the block shape in pixel, clockwise on screen; circles are approximated
+    pub fn outline(&self) -> Vec<(f32, f32)> {
+        let graphics::Rect { x, y, w, h } = self.rect;
+        let (right, bottom) = (x + w, y + h);
+        match self.shape {
+            Shape::Rect => vec![(x, y), (right, y), (right, bottom), (x, bottom)],
+            Shape::Half => vec![(x, y + h / 2.0), (right, y + h / 2.0), (right, bottom), (x, bottom)],
+            Shape::Triangle(Corner::TopLeft) => vec![(x, y), (right, y), (x, bottom)],
+            Shape::Triangle(Corner::TopRight) => vec![(x, y), (right, y), (right, bottom)],
+            Shape::Triangle(Corner::BottomLeft) => vec![(x, y), (right, bottom), (x, bottom)],
+            Shape::Triangle(Corner::BottomRight) => vec![(right, y), (right, bottom), (x, bottom)],
+            Shape::Circle => (0..CIRCLE_POINTS)
+                .map(|ix| {
+                    let (sin, cos) = (ix as f32 / CIRCLE_POINTS as f32 * TAU).sin_cos();
+                    (x + w / 2.0 + cos * h / 2.0, y + h / 2.0 + sin * h / 2.0)
+                })
+                .collect(),
+        }
+    }
+
+    // if a ball with the given center and radius touches the block shape,
+    // the unit vector pointing from the block to the ball, to bounce off
+    pub fn normal(&self, center: (f32, f32), radius: f32) -> Option<(f32, f32)> {
+        let (cx, cy) = center;
+        if self.shape == Shape::Circle {
+            let (dx, dy) = (cx - self.rect.x - self.rect.w / 2.0, cy - self.rect.y - self.rect.h / 2.0);
+            let dist = (dx * dx + dy * dy).sqrt();
+            if dist > radius + self.rect.h / 2.0 {
+                return None;
+            }
+            if dist == 0.0 {
+                return Some((0.0, 1.0));
+            }
+            return Some((dx / dist, dy / dist));
+        }
+        let points = self.outline();
+        let mut inside = true;
+        // squared distance to the closest point of the outline, that point and the normal of its side
+        let mut closest = (f32::MAX, (0.0, 0.0), (0.0, 0.0));
+        for (ix, &(ax, ay)) in points.iter().enumerate() {
+            let (bx, by) = points[(ix + 1) % points.len()];
+            let (ex, ey) = (bx - ax, by - ay);
+            // clockwise on screen: the inside is on the right of each side
+            if ex * (cy - ay) - ey * (cx - ax) < 0.0 {
+                inside = false;
+            }
+            let t = (((cx - ax) * ex + (cy - ay) * ey) / (ex * ex + ey * ey)).clamp(0.0, 1.0);
+            let (px, py) = (ax + ex * t, ay + ey * t);
+            let dist = (cx - px) * (cx - px) + (cy - py) * (cy - py);
+            if dist < closest.0 {
+                let len = (ex * ex + ey * ey).sqrt();
+                closest = (dist, (px, py), (ey / len, -ex / len));
+            }
+        }
+        let (dist, (px, py), side) = closest;
+        if inside || dist == 0.0 {
+            // the ball center went through: push it back out of the nearest side
+            return Some(side);
+        }
+        if dist > radius * radius {
+            return None;
+        }
+        let dist = dist.sqrt();
+        Some(((cx - px) / dist, (cy - py) / dist))
+    }
 }
 
 impl PartialEq for Block {
@@ -30,110 +195,515 @@ impl Hash for Block {
     }
 }
 
+// How the ball leaves the paddle, angles being from the vertical, in radians
+#[derive(Clone, Copy, Debug)]
+pub struct Deflection {
+    pub min_angle: f32, // angle when the ball hits the paddle center
+    pub max_angle: f32, // angle when the ball hits the paddle ends
+    pub english: f32,   // angle added when the paddle moves at normal speed
+}
+
+impl Default for Deflection {
+    fn default() -> Self {
+        Self {
+            min_angle: MIN_ANGLE.to_radians(),
+            max_angle: MAX_ANGLE.to_radians(),
+            english: 0.0,
+        }
+    }
+}
+
+// What opens a warp exit
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum WarpCondition {
+    PowerUp,         // the paddle caught a power-up
+    Time(f32),       // the ball was in play for so many seconds
+    Block(i32, i32), // the hidden block at this grid position was broken
+}
+
+// Exit at the bottom of a side wall, that the paddle can go through once open
+#[derive(Clone, Copy, Debug)]
+pub struct Warp {
+    pub side: Direction,          // wall the exit is in
+    pub skip: u32,                // levels skipped by going through
+    pub condition: WarpCondition, // what opens it
+}
+
+// When the ball goes faster during a level, until a ball is lost
+#[derive(Clone, Copy, Debug)]
+pub struct SpeedUp {
+    pub step: f32,        // speed added each time
+    pub paddle_hits: u32, // speed up every so many paddle hits, 0 for never
+    pub top: bool,        // speed up the first time the ball hits the top wall
+    pub row: Option<i32>, // speed up the first time a block in this row or above is broken
+    pub max_speed: f32,   // fastest the ball can go
+}
+
+// Level layout, as read from a level file
+#[derive(Clone, Debug)]
+pub struct LevelDef {
+    pub name: String,                // level name
+    pub theme: String,               // name of the color theme
+    pub speed: f32,                  // initial speed of the ball
+    pub deflection: Deflection,      // paddle bounces
+    pub speedup: SpeedUp,            // speed increase during the level
+    pub enemies: Option<EnemySpawn>, // enemies sent during the level
+    pub boss: Option<u32>,           // hit points of the boss, if there is one
+    pub laser_chance: f32,           // chance that a broken block drops a laser capsule
+    pub catch_chance: f32,           // chance that a broken block drops a catch capsule
+    pub warp: Option<Warp>,          // secret exit to a later level
+    pub fixtures: Vec<Fixture>,      // bumpers, one-way walls, portals and speed zones
+    pub blocks: Vec<Block>,          // blocks
+}
+
+// Error in a level or theme file
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize, // line number, starting at 1
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new<S: Into<String>>(line: usize, message: S) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl LevelDef {
+    // Parse a level file
+    // Lines are either "key value" settings or, after a "blocks" line, rows of the block grid
+    // until an empty line. In the grid '.' is empty, 'L' is light, 'D' is dark
+    // and '#' alternates light and dark. Lines starting with '#' outside of the grid are comments.
+    // 'H' is a hidden block, that the ball bounces on and breaks like the others.
+    // Other shapes alternate light and dark too: '_' is a half block, 'o' a circle, '/' and '\'
+    // are triangles filled below their slope, 'F' and '7' triangles filled above it.
+    // Paddle bounces are set with "min_angle", "max_angle" and "english", in degrees.
+    // The ball speeds up by "speedup_step" every "speedup_paddle" paddle hits, when
+    // "speedup_top" is true and it first hits the top, or when it first breaks a block in
+    // "speedup_row" or above, up to "max_speed" (twice the initial speed by default).
+    // Blocks from a first to a last row can move, see `parse_motion`.
+    // "enemies <max> <seconds> <drift|zigzag|chase>" sends enemies regularly.
+    // "boss <hit points>" makes a boss level, that needs no blocks.
+    // "laser_chance <percent>" and "catch_chance <percent>" are how often a broken block
+    // drops a capsule with that power-up.
+    // "warp <left|right> <levels skipped> <condition>" adds a warp exit, see `parse_warp`.
+    // "bumper", "one_way", "portal" and "speed_zone" add fixtures, see `parse_fixture`.
+    pub fn parse(text: &str) -> Result<LevelDef, ParseError> {
+        let mut def = LevelDef {
+            name: String::new(),
+            theme: DEFAULT_THEME.to_owned(),
+            speed: BALL_SPEED,
+            deflection: Deflection::default(),
+            speedup: SpeedUp {
+                step: SPEEDUP_STEP,
+                paddle_hits: 0,
+                top: false,
+                row: None,
+                max_speed: 0.0,
+            },
+            enemies: None,
+            boss: None,
+            laser_chance: 0.0,
+            catch_chance: 0.0,
+            warp: None,
+            fixtures: Vec::new(),
+            blocks: Vec::new(),
+        };
+        let mut max_speed = None;
+        let mut motions = Vec::new();
+        // next grid row when reading the grid
+        let mut row: Option<i32> = None;
+        let mut line_no = 0;
+        for line in text.lines() {
+            line_no += 1;
+            let line = line.trim_end();
+            if let Some(j) = row {
+                if line.is_empty() {
+                    row = None;
+                } else {
+                    parse_row(line_no, j, line, &mut def.blocks)?;
+                    row = Some(j + 1);
+                }
+                continue;
+            }
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let key = words.next().unwrap_or_default();
+            let args: Vec<&str> = words.collect();
+            match key {
+                "name" => def.name = args.join(" "),
+                "theme" => def.theme = parse_value(line_no, &args)?,
+                "speed" => def.speed = parse_value(line_no, &args)?,
+                "min_angle" => def.deflection.min_angle = parse_angle(line_no, &args)?,
+                "max_angle" => def.deflection.max_angle = parse_angle(line_no, &args)?,
+                "english" => def.deflection.english = parse_angle(line_no, &args)?,
+                "speedup_step" => def.speedup.step = parse_value(line_no, &args)?,
+                "speedup_paddle" => def.speedup.paddle_hits = parse_value(line_no, &args)?,
+                "speedup_top" => def.speedup.top = parse_value(line_no, &args)?,
+                "speedup_row" => def.speedup.row = Some(parse_value(line_no, &args)?),
+                "max_speed" => max_speed = Some(parse_value(line_no, &args)?),
+                "laser_chance" => def.laser_chance = parse_percent(line_no, &args)?,
+                "catch_chance" => def.catch_chance = parse_percent(line_no, &args)?,
+                "warp" => def.warp = Some(parse_warp(line_no, &args)?),
+                "bumper" | "one_way" | "portal" | "speed_zone" => {
+                    def.fixtures.push(parse_fixture(line_no, key, &args)?)
+                }
+                "boss" => def.boss = Some(parse_value(line_no, &args)?),
+                "enemies" => def.enemies = Some(parse_enemies(line_no, &args)?),
+                "slide" | "rotate" | "descend" => motions.push((line_no, key, args)),
+                "blocks" => row = Some(0),
+                _ => return Err(ParseError::new(line_no, format!("unknown setting '{}'", key))),
+            }
+        }
+        if def.blocks.iter().all(|b| b.kind == BlockKind::Hidden) && def.boss.is_none() {
+            return Err(ParseError::new(line_no, "no blocks"));
+        }
+        if def.boss == Some(0) {
+            return Err(ParseError::new(line_no, "the boss needs hit points"));
+        }
+        if let Some(Warp {
+            condition: WarpCondition::Block(i, j),
+            ..
+        }) = def.warp
+        {
+            if !def.blocks.iter().any(|b| b.i == i && b.j == j && b.kind == BlockKind::Hidden) {
+                return Err(ParseError::new(line_no, format!("no hidden block at {} {} to open the warp", i, j)));
+            }
+        }
+        for (line_no, key, args) in motions {
+            parse_motion(line_no, key, &args, &mut def.blocks)?;
+        }
+        if def.deflection.min_angle > def.deflection.max_angle {
+            return Err(ParseError::new(line_no, "min_angle is larger than max_angle"));
+        }
+        def.speedup.max_speed = max_speed.unwrap_or(def.speed * 2.0);
+        if def.speedup.max_speed < def.speed {
+            return Err(ParseError::new(line_no, "max_speed is lower than speed"));
+        }
+        Ok(def)
+    }
+}
+
+// parse a row of the block grid
+fn parse_row(line_no: usize, j: i32, line: &str, blocks: &mut Vec<Block>) -> Result<(), ParseError> {
+    if j >= ROWS {
+        return Err(ParseError::new(line_no, format!("more than {} rows", ROWS)));
+    }
+    for (i, c) in line.chars().enumerate() {
+        let i = i as i32;
+        if i >= COLUMNS {
+            return Err(ParseError::new(line_no, format!("more than {} columns", COLUMNS)));
+        }
+        let checkered = BlockKind::checkered(i, j);
+        let (kind, shape) = match c {
+            '.' | ' ' => continue,
+            '#' => (checkered, Shape::Rect),
+            'L' => (BlockKind::Light, Shape::Rect),
+            'D' => (BlockKind::Dark, Shape::Rect),
+            'H' => (BlockKind::Hidden, Shape::Rect),
+            '_' => (checkered, Shape::Half),
+            'o' => (checkered, Shape::Circle),
+            '/' => (checkered, Shape::Triangle(Corner::BottomRight)),
+            '\\' => (checkered, Shape::Triangle(Corner::BottomLeft)),
+            'F' => (checkered, Shape::Triangle(Corner::TopLeft)),
+            '7' => (checkered, Shape::Triangle(Corner::TopRight)),
+            _ => return Err(ParseError::new(line_no, format!("unknown block '{}'", c))),
+        };
+        blocks.push(Block::new(i, j, kind, shape));
+    }
+    Ok(())
+}
+
+// parse the single argument of a setting
+pub fn parse_value<T: FromStr>(line_no: usize, args: &[&str]) -> Result<T, ParseError> {
+    match args {
+        [arg] => arg
+            .parse()
+            .map_err(|_| ParseError::new(line_no, format!("invalid value '{}'", arg))),
+        _ => Err(ParseError::new(line_no, "expected a single value")),
+    }
+}
+
+// parse the motion of the blocks between two rows, and set it on these blocks:
+// "slide <first row> <last row> <distance> <period>",
+// "rotate <first row> <last row> <period>" around the center of these blocks,
+// "descend <first row> <last row> <speed>"
+fn parse_motion(line_no: usize, key: &str, args: &[&str], blocks: &mut [Block]) -> Result<(), ParseError> {
+    if args.len() < 2 {
+        return Err(ParseError::new(line_no, "expected a first and a last row"));
+    }
+    let first: i32 = parse_value(line_no, &args[0..1])?;
+    let last: i32 = parse_value(line_no, &args[1..2])?;
+    let args = &args[2..];
+    let in_rows = |b: &Block| b.j >= first && b.j <= last;
+    let count = blocks.iter().filter(|b| in_rows(b)).count();
+    if count == 0 {
+        return Err(ParseError::new(line_no, format!("no blocks in rows {} to {}", first, last)));
+    }
+    let motion = match (key, args) {
+        ("slide", [distance, period]) => Motion::Slide {
+            distance: parse_value(line_no, &[distance])?,
+            period: parse_period(line_no, period)?,
+        },
+        ("rotate", [period]) => {
+            let (mut x, mut y) = (0.0, 0.0);
+            for b in blocks.iter().filter(|b| in_rows(b)) {
+                x += b.rect.x + b.rect.w / 2.0;
+                y += b.rect.y + b.rect.h / 2.0;
+            }
+            Motion::Rotate {
+                center: (x / count as f32, y / count as f32),
+                period: parse_period(line_no, period)?,
+            }
+        }
+        ("descend", [speed]) => Motion::Descend {
+            speed: parse_value(line_no, &[speed])?,
+        },
+        _ => return Err(ParseError::new(line_no, format!("wrong number of values for '{}'", key))),
+    };
+    for b in blocks.iter_mut().filter(|b| in_rows(b)) {
+        b.motion = motion;
+    }
+    Ok(())
+}
+
+// parse the enemies sent by a level: most at the same time, seconds between two, and how they move
+fn parse_enemies(line_no: usize, args: &[&str]) -> Result<EnemySpawn, ParseError> {
+    match args {
+        [max, every, behaviour] => Ok(EnemySpawn {
+            max: parse_value(line_no, &[max])?,
+            every: parse_period(line_no, every)?,
+            behaviour: behaviour
+                .parse()
+                .map_err(|e: String| ParseError::new(line_no, e))?,
+        }),
+        _ => Err(ParseError::new(line_no, "expected the number of enemies, seconds and movement")),
+    }
+}
+
+// parse a warp exit: the side, the levels skipped, and "powerup", "time <seconds>"
+// or "block <column> <row>" for what opens it, that block being a hidden one
+fn parse_warp(line_no: usize, args: &[&str]) -> Result<Warp, ParseError> {
+    if args.len() < 3 {
+        return Err(ParseError::new(line_no, "expected a side, levels to skip and a condition"));
+    }
+    let side = match args[0] {
+        "left" => Direction::Left,
+        "right" => Direction::Right,
+        _ => return Err(ParseError::new(line_no, "the warp side must be left or right")),
+    };
+    let skip = parse_value(line_no, &args[1..2])?;
+    let condition = match &args[2..] {
+        ["powerup"] => WarpCondition::PowerUp,
+        ["time", secs] => WarpCondition::Time(parse_value(line_no, &[secs])?),
+        ["block", i, j] => WarpCondition::Block(parse_value(line_no, &[i])?, parse_value(line_no, &[j])?),
+        _ => return Err(ParseError::new(line_no, "unknown warp condition")),
+    };
+    Ok(Warp { side, skip, condition })
+}
+
+// parse a fixture, positions and sizes being in pixel, positions in the playfield above
+// the paddle and sizes positive:
+// "bumper <x> <y> <radius>",
+// "one_way <x> <y> <width> <up|down>" with the direction the ball can go through,
+// "portal <x1> <y1> <x2> <y2>" with the centers of both ends,
+// "speed_zone <x> <y> <width> <height> <factor>"
+fn parse_fixture(line_no: usize, key: &str, args: &[&str]) -> Result<Fixture, ParseError> {
+    let value = |arg: &str| parse_value::<f32>(line_no, &[arg]);
+    // sizes and speed factors below zero make no sense, and a zero factor stops the ball for good
+    let positive = |arg: &str, what: &str| match value(arg)? {
+        v if v > 0.0 => Ok(v),
+        _ => Err(ParseError::new(line_no, format!("the {} must be positive", what))),
+    };
+    // positions must be in the playfield, above the paddle
+    let x_pos = |arg: &str| match value(arg)? {
+        v if (0.0..=WIDTH).contains(&v) => Ok(v),
+        _ => Err(ParseError::new(line_no, format!("x must be between 0 and {}", WIDTH))),
+    };
+    let y_pos = |arg: &str| match value(arg)? {
+        v if (0.0..=FIXTURE_BOTTOM).contains(&v) => Ok(v),
+        _ => Err(ParseError::new(line_no, format!("y must be between 0 and {}", FIXTURE_BOTTOM))),
+    };
+    let fixture = match (key, args) {
+        ("bumper", [x, y, radius]) => Fixture::Bumper {
+            center: (x_pos(x)?, y_pos(y)?),
+            radius: positive(radius, "radius")?,
+        },
+        ("one_way", [x, y, width, direction]) => {
+            let up = match *direction {
+                "up" => true,
+                "down" => false,
+                _ => return Err(ParseError::new(line_no, "the direction must be up or down")),
+            };
+            Fixture::one_way(x_pos(x)?, y_pos(y)?, positive(width, "width")?, up)
+        }
+        ("portal", [x1, y1, x2, y2]) => Fixture::Portal {
+            ends: [(x_pos(x1)?, y_pos(y1)?), (x_pos(x2)?, y_pos(y2)?)],
+        },
+        ("speed_zone", [x, y, width, height, factor]) => Fixture::SpeedZone {
+            rect: graphics::Rect::new(
+                x_pos(x)?,
+                y_pos(y)?,
+                positive(width, "width")?,
+                positive(height, "height")?,
+            ),
+            factor: positive(factor, "speed factor")?,
+        },
+        _ => return Err(ParseError::new(line_no, format!("wrong number of values for '{}'", key))),
+    };
+    Ok(fixture)
+}
+
+// parse a percentage, returning a fraction between 0 and 1
+fn parse_percent(line_no: usize, args: &[&str]) -> Result<f32, ParseError> {
+    let percent: f32 = parse_value(line_no, args)?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(ParseError::new(line_no, "percentages must be between 0 and 100"));
+    }
+    Ok(percent / 100.0)
+}
+
+// parse a period in seconds, that cannot be zero
+fn parse_period(line_no: usize, arg: &str) -> Result<f32, ParseError> {
+    let period: f32 = parse_value(line_no, &[arg])?;
+    if period <= 0.0 {
+        return Err(ParseError::new(line_no, "periods must be positive"));
+    }
+    Ok(period)
+}
+
+// parse an angle in degrees, between vertical and horizontal
+fn parse_angle(line_no: usize, args: &[&str]) -> Result<f32, ParseError> {
+    let degrees: f32 = parse_value(line_no, args)?;
+    if !(0.0..90.0).contains(&degrees) {
+        return Err(ParseError::new(line_no, "angles must be between 0 and 90 degrees"));
+    }
+    Ok(degrees.to_radians())
+}
+
+// level files in a directory, sorted by name
+pub fn level_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension() == Some(OsStr::new("txt")))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// read all level files in a directory, without going through the game context
+pub fn read_levels(dir: &Path) -> Result<Vec<LevelDef>, String> {
+    let files = level_files(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    if files.is_empty() {
+        return Err(format!("No level file in {}", dir.display()));
+    }
+    let mut defs = Vec::new();
+    for path in files {
+        let error = |e: &dyn fmt::Display| format!("{}: {}", path.display(), e);
+        let text = fs::read_to_string(&path).map_err(|e| error(&e))?;
+        defs.push(LevelDef::parse(&text).map_err(|e| error(&e))?);
+    }
+    Ok(defs)
+}
+
 // Level definition
 pub struct Level {
-    pub index: u32,             // level number
-    pub blocks: HashSet<Block>, // block position
-    pub ball_speed: f32,        // speed of the ball
+    pub index: u32,                  // level number
+    pub name: String,                // level name
+    pub theme: String,               // name of the color theme
+    pub blocks: HashSet<Block>,      // block position
+    pub ball_speed: f32,             // speed of the ball
+    pub deflection: Deflection,      // paddle bounces
+    pub speedup: SpeedUp,            // speed increase during the level
+    pub enemies: Option<EnemySpawn>, // enemies sent during the level
+    pub boss: Option<u32>,           // hit points of the boss, if there is one
+    pub laser_chance: f32,           // chance that a broken block drops a laser capsule
+    pub catch_chance: f32,           // chance that a broken block drops a catch capsule
+    pub warp: Option<Warp>,          // secret exit to a later level
+    pub fixtures: Vec<Fixture>,      // bumpers, one-way walls, portals and speed zones
+    pub moving: bool,                // do some blocks move?
 }
 
-// Get the next level, based on the index
-pub fn next_level(ix: u32) -> Level {
-    let mut blocks = HashSet::new();
-
-    let mut speed = BALL_SPEED;
+impl Level {
+    // are all blocks that count broken? Hidden blocks may be left
+    pub fn cleared(&self) -> bool {
+        self.blocks.iter().all(|b| b.kind == BlockKind::Hidden)
+    }
+}
 
-    let num_levels = 3;
+// Get the next level, based on the index
+pub fn next_level(defs: &[LevelDef], ix: u32) -> Level {
+    let num_levels = defs.len() as u32;
 
     // get the level disposition
-    let lvl = ix % num_levels;
+    let def = &defs[(ix % num_levels) as usize];
+    let mut speed = def.speed;
+    let mut speedup = def.speedup;
     // speed increases when we go back to first level
     let multi = ix / num_levels;
     if multi > 1 {
         speed *= multi as f32;
-    }
-
-    // build proper level
-    match lvl {
-        0 => level_1(&mut blocks),
-        1 => level_2(&mut blocks),
-        3 => level_3(&mut blocks),
-        _ => panic!("No level for {}", lvl),
+        speedup.max_speed *= multi as f32;
     }
 
     Level {
         index: ix,
-        blocks,
+        name: def.name.clone(),
+        theme: def.theme.clone(),
+        blocks: def.blocks.iter().cloned().collect(),
         ball_speed: speed,
+        deflection: def.deflection,
+        speedup,
+        enemies: def.enemies,
+        boss: def.boss,
+        laser_chance: def.laser_chance,
+        catch_chance: def.catch_chance,
+        warp: def.warp,
+        fixtures: def.fixtures.clone(),
+        moving: def.blocks.iter().any(|b| b.motion != Motion::Still),
     }
 }
 
-// Level 1
-fn level_1(blocks: &mut HashSet<Block>) {
-    for j in 0..3 {
-        for x in 0..(5 - j * 2) {
-            let i = 8 + j + x;
-            let minx = (i as f32) * BLOCK_WIDTH;
-            let miny = (j as f32) * BLOCK_HEIGHT;
-            let color = if i % 2 == j % 2 { GRAY } else { DARKGRAY };
-            blocks.insert(Block {
-                i,
-                j,
-                rect: graphics::Rect::new(minx, miny, BLOCK_WIDTH, BLOCK_HEIGHT),
-                fill: color,
-                stroke: graphics::BLACK,
-            });
-        }
-    }
-}
-
-// Level 2
-fn level_2(blocks: &mut HashSet<Block>) {
-    for (i, j) in &[
-        (10, 0),
-        (9, 1),
-        (11, 1),
-        (8, 2),
-        (12, 2),
-        (9, 3),
-        (11, 3),
-        (10, 4),
-    ] {
-        let minx = (*i as f32) * BLOCK_WIDTH;
-        let miny = (*j as f32) * BLOCK_HEIGHT;
-        let color = if i % 2 == j % 2 { GRAY } else { DARKGRAY };
-        blocks.insert(Block {
-            i:*i,
-            j:*j,
-            rect: graphics::Rect::new(minx, miny, BLOCK_WIDTH, BLOCK_HEIGHT),
-            fill: color,
-            stroke: graphics::BLACK,
-        });
-    }
-}
-
-// Level 3
-fn level_3(blocks: &mut HashSet<Block>) {
-    for i in 0..20 {
-        for j in 0..5 {
-            let minx = (i as f32) * BLOCK_WIDTH;
-            let miny = (j as f32) * BLOCK_HEIGHT;
-            let color = if i % 2 == j % 2 { GRAY } else { DARKGRAY };
-            blocks.insert(Block {
-                i,
-                j,
-                rect: graphics::Rect::new(minx, miny, BLOCK_WIDTH, BLOCK_HEIGHT),
-                fill: color,
-                stroke: graphics::BLACK,
-            });
-        }
-    }
-}
+// Theme used when a level does not name one
+pub const DEFAULT_THEME: &str = "default";
 
 // Initial ball speed
-const BALL_SPEED: f32 = 5.0;
+pub const BALL_SPEED: f32 = 5.0;
+
+// Default speed added when the ball speeds up
+const SPEEDUP_STEP: f32 = 0.5;
+
+// Default paddle bounce angles, in degrees from the vertical
+const MIN_ANGLE: f32 = 10.0;
+const MAX_ANGLE: f32 = 60.0;
 
 // Default lock width
-const BLOCK_WIDTH: f32 = 40.0;
+pub const BLOCK_WIDTH: f32 = 40.0;
 // Default block height
-const BLOCK_HEIGHT: f32 = 20.0;
+pub const BLOCK_HEIGHT: f32 = 20.0;
+
+// Lowest point descending blocks go to, well above the paddle
+const DESCEND_LIMIT: f32 = 300.0;
+
+// Lowest position of a fixture, the top of the paddle
+const FIXTURE_BOTTOM: f32 = 400.0;
+
+// Corners used to draw a circle block as a polygon
+const CIRCLE_POINTS: u32 = 16;
+
+// Size of the block grid
+const COLUMNS: i32 = 20;
+const ROWS: i32 = 15;
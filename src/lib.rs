@@ -0,0 +1,44 @@
+//! Game logic shared by the game and the tools: levels, simulation, scoring and controllers
+
+pub mod boss;
+pub mod controller;
+pub mod enemy;
+pub mod fixture;
+pub mod level;
+pub mod projectile;
+pub mod replay;
+pub mod scoring;
+pub mod sim;
+
+use ggez::graphics;
+
+pub const WIDTH: f32 = 800.0;
+pub const HEIGHT: f32 = 450.0;
+
+pub const LIGHTGRAY: graphics::Color = graphics::Color {
+    r: 0.83,
+    g: 0.83,
+    b: 0.83,
+    a: 1.0,
+};
+
+pub const GRAY: graphics::Color = graphics::Color {
+    r: 0.5,
+    g: 0.5,
+    b: 0.5,
+    a: 1.0,
+};
+
+pub const DARKGRAY: graphics::Color = graphics::Color {
+    r: 0.33,
+    g: 0.33,
+    b: 0.33,
+    a: 1.0,
+};
+
+pub const RED: graphics::Color = graphics::Color {
+    r: 0.83,
+    g: 0.13,
+    b: 0.18,
+    a: 1.0,
+};
@@ -6,20 +6,59 @@ use ggez::conf;
 use ggez::event;
 use ggez::graphics;
 use ggez::nalgebra as na;
+use ggez::timer;
 use ggez::{Context, GameResult};
 use std::env;
 use std::path;
+use std::process;
+use std::rc::Rc;
 use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+mod assets;
+use assets::{Assets, Loader};
+
+mod audio;
+use audio::{AudioManager, Music, Sfx};
+
+mod bench;
+
+mod cli;
+use cli::Options;
+
+mod console;
+use console::{Command, Commands, ConsoleState};
+
+mod debug;
+use debug::DebugOverlay;
 
 mod play;
 use play::PlayState;
 
-pub mod level;
+mod reload;
+use reload::Watcher;
+
+mod summary;
+mod theme;
+
+mod time_attack;
+use time_attack::TimeAttack;
+
+mod transition;
+use transition::{Animation, Effect};
+
+use rust_blocks::level::{self, next_level};
+use rust_blocks::replay::{self, Replay};
+use rust_blocks::sim::{self, LIVES, TICKS_PER_SECOND};
+use rust_blocks::{boss, controller, enemy, fixture, projectile, scoring};
+use rust_blocks::{DARKGRAY, GRAY, HEIGHT, LIGHTGRAY, RED, WIDTH};
 
 // Transition to a different game state
 enum Transition {
     Push(Box<dyn InnerState>),
     Replace(Box<dyn InnerState>),
+    Animate(Box<dyn InnerState>, Effect), // replace with an animated effect
+    Loaded(Rc<Assets>),                   // all assets are loaded, start the game
     Pop,
     None,
 }
@@ -27,31 +66,147 @@ enum Transition {
 // Game state
 trait InnerState: event::EventHandler {
     // transition on key press
-    fn transition(&self, font: graphics::Font, keycode: event::KeyCode) -> Transition;
+    fn transition(&self, keycode: event::KeyCode) -> Transition;
 
     // transition on state change
-    fn state_transition(&self, _font: graphics::Font) -> Transition {
+    fn state_transition(&self) -> Transition {
         Transition::None
     }
+
+    // sound effects to play since last call
+    fn take_sounds(&mut self) -> Vec<Sfx> {
+        Vec::new()
+    }
+
+    // music to play, None to keep the current one
+    fn music(&self) -> Option<Music> {
+        None
+    }
+
+    // state to use instead after levels or themes were reloaded, None to keep this one
+    fn reload(&self) -> Option<Box<dyn InnerState>> {
+        None
+    }
+
+    // console commands typed since last call
+    fn take_commands(&mut self) -> Vec<Command> {
+        Vec::new()
+    }
+
+    // where console commands are run, None if this state does not accept any
+    fn commands(&mut self) -> Option<&mut dyn Commands> {
+        None
+    }
+
+    // result of a command typed in this state
+    fn command_result(&mut self, _result: Result<String, String>) {}
+
+    // true if keys are typed text, so global keys should not apply
+    fn takes_text(&self) -> bool {
+        false
+    }
+
+    // name shown in the debug overlay
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    // extra details for the debug overlay
+    fn draw_debug(&self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+}
+
+// Loading screen, shown while assets are loaded
+struct LoadingState {
+    loader: Loader,
+    title_text: graphics::Text,
+}
+
+impl LoadingState {
+    fn new(loader: Loader) -> Self {
+        let title_text = graphics::Text::new(("Loading...", loader.font, 36.0));
+        Self { loader, title_text }
+    }
+}
+
+impl event::EventHandler for LoadingState {
+    // load one asset per update, so the screen shows progress
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.loader.step(ctx)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, LIGHTGRAY);
+        let (w, _h) = self.title_text.dimensions(ctx);
+        let dest_point = na::Point2::new(WIDTH / 2.0 - (w as f32 / 2.0), 150.0);
+        graphics::draw(ctx, &self.title_text, (dest_point, RED))?;
+
+        // progress bar
+        let bar = graphics::Rect::new(WIDTH / 4.0, 220.0, WIDTH / 2.0, 20.0);
+        let mb = &mut graphics::MeshBuilder::new();
+        mb.rectangle(
+            graphics::DrawMode::Fill(graphics::FillOptions::default()),
+            graphics::Rect::new(bar.x, bar.y, bar.w * self.loader.progress(), bar.h),
+            GRAY,
+        );
+        mb.rectangle(
+            graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
+            bar,
+            DARKGRAY,
+        );
+        let m = &mb.build(ctx)?;
+        graphics::draw(ctx, m, graphics::DrawParam::new())?;
+
+        if let Some(current) = self.loader.current() {
+            let file_text = graphics::Text::new((current, self.loader.font, 18.0));
+            let (w, _h) = file_text.dimensions(ctx);
+            let dest_point = na::Point2::new(WIDTH / 2.0 - (w as f32 / 2.0), 260.0);
+            graphics::draw(ctx, &file_text, (dest_point, DARKGRAY))?;
+        }
+        Ok(())
+    }
+}
+
+impl InnerState for LoadingState {
+    fn transition(&self, _keycode: event::KeyCode) -> Transition {
+        Transition::None
+    }
+
+    fn state_transition(&self) -> Transition {
+        match self.loader.assets() {
+            Some(assets) => Transition::Loaded(assets),
+            None => Transition::None,
+        }
+    }
 }
 
 // Starting state
 struct StartState {
+    assets: Rc<Assets>,
+    options: Rc<Options>,
     title_text: graphics::Text,
     start_text: graphics::Text,
+    attack_text: graphics::Text,
     last_update: Instant,
     show_start: bool,
+    idle_since: Instant, // last key press, to start the demo
 }
 
 impl StartState {
-    fn new(font: graphics::Font) -> Self {
-        let title_text = graphics::Text::new(("BLOCKS", font, 72.0));
-        let start_text = graphics::Text::new(("Press <SPACE> to start", font, 36.0));
+    fn new(assets: Rc<Assets>, options: Rc<Options>) -> Self {
+        let title_text = graphics::Text::new(("BLOCKS", assets.font, 72.0));
+        let start_text = graphics::Text::new(("Press <SPACE> to start", assets.font, 36.0));
+        let attack_text = graphics::Text::new(("Press <T> for time attack", assets.font, 25.0));
         Self {
+            assets,
+            options,
             title_text,
             start_text,
+            attack_text,
             last_update: Instant::now(),
             show_start: true,
+            idle_since: Instant::now(),
         }
     }
 }
@@ -75,18 +230,162 @@ impl event::EventHandler for StartState {
             let dest_point = na::Point2::new(WIDTH / 2.0 - (w as f32 / 2.0), 180.0);
             graphics::draw(ctx, &self.start_text, (dest_point, graphics::BLACK))?;
         }
+        let (w, _h) = self.attack_text.dimensions(ctx);
+        let dest_point = na::Point2::new(WIDTH / 2.0 - (w as f32 / 2.0), 240.0);
+        graphics::draw(ctx, &self.attack_text, (dest_point, DARKGRAY))?;
 
         Ok(())
     }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        _keycode: event::KeyCode,
+        _keymod: event::KeyMods,
+        _repeat: bool,
+    ) {
+        self.idle_since = Instant::now();
+    }
 }
 
 impl InnerState for StartState {
-    fn transition(&self, font: graphics::Font, keycode: event::KeyCode) -> Transition {
+    fn transition(&self, keycode: event::KeyCode) -> Transition {
+        if keycode == event::KeyCode::Space {
+            let intro = IntroState::new(
+                self.assets.clone(),
+                self.options.clone(),
+                self.options.level,
+                0,
+                LIVES,
+                None,
+            );
+            return Transition::Animate(Box::new(intro), Effect::Fade);
+        }
+        // time attack always goes through all levels from the first
+        if keycode == event::KeyCode::T {
+            let levels = self.assets.levels().len() as u32;
+            let attack = TimeAttack::new(levels, &self.options.best_times);
+            let intro = IntroState::new(
+                self.assets.clone(),
+                self.options.clone(),
+                0,
+                0,
+                LIVES,
+                Some(attack),
+            );
+            return Transition::Animate(Box::new(intro), Effect::Fade);
+        }
+        Transition::None
+    }
+
+    // attract mode: the computer plays a random level when nobody does
+    fn state_transition(&self) -> Transition {
+        if Instant::now() - self.idle_since >= Duration::from_secs(ATTRACT_SECS) {
+            let demo = PlayState::demo(self.assets.clone(), self.options.clone());
+            return Transition::Animate(Box::new(demo), Effect::Fade);
+        }
+        Transition::None
+    }
+
+    fn music(&self) -> Option<Music> {
+        Some(Music::Title)
+    }
+}
+
+// Intro card shown before each level starts
+struct IntroState {
+    assets: Rc<Assets>,
+    options: Rc<Options>,
+    title_text: graphics::Text,
+    name_text: graphics::Text,
+    index: u32,
+    score: u32,
+    lives: u32,
+    attack: Option<TimeAttack>, // time attack run, if playing one
+    start: Instant,
+}
+
+impl IntroState {
+    fn new(
+        assets: Rc<Assets>,
+        options: Rc<Options>,
+        index: u32,
+        score: u32,
+        lives: u32,
+        attack: Option<TimeAttack>,
+    ) -> Self {
+        let title_text = graphics::Text::new((format!("Level {}", index + 1), assets.font, 72.0));
+        let level = next_level(&assets.levels(), index);
+        let name_text = graphics::Text::new((level.name, assets.font, 36.0));
+        Self {
+            assets,
+            options,
+            title_text,
+            name_text,
+            index,
+            score,
+            lives,
+            attack,
+            start: Instant::now(),
+        }
+    }
+
+    // the level itself, with its own transition
+    fn play(&self) -> Transition {
+        let mut play = PlayState::new(
+            self.assets.clone(),
+            self.options.clone(),
+            self.index,
+            self.score,
+            self.lives,
+        );
+        play.attack = self.attack.clone();
+        Transition::Animate(Box::new(play), Effect::Wipe)
+    }
+}
+
+impl event::EventHandler for IntroState {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, LIGHTGRAY);
+        let (w, h) = self.title_text.dimensions(ctx);
+        let dest_point = na::Point2::new(
+            WIDTH / 2.0 - (w as f32 / 2.0),
+            HEIGHT / 2.0 - (h as f32 / 2.0),
+        );
+        graphics::draw(ctx, &self.title_text, (dest_point, RED))?;
+        let (w, _h) = self.name_text.dimensions(ctx);
+        let dest_point = na::Point2::new(
+            WIDTH / 2.0 - (w as f32 / 2.0),
+            dest_point.y + h as f32 + 10.0,
+        );
+        graphics::draw(ctx, &self.name_text, (dest_point, DARKGRAY))?;
+        Ok(())
+    }
+}
+
+impl InnerState for IntroState {
+    // space skips the intro
+    fn transition(&self, keycode: event::KeyCode) -> Transition {
         if keycode == event::KeyCode::Space {
-            return Transition::Replace(Box::new(PlayState::new(font, 0, 0)));
+            return self.play();
+        }
+        Transition::None
+    }
+
+    fn state_transition(&self) -> Transition {
+        if Instant::now() - self.start >= Duration::from_millis(MILLIS_PER_INTRO) {
+            return self.play();
         }
         Transition::None
     }
+
+    fn music(&self) -> Option<Music> {
+        Some(Music::Level(self.index))
+    }
 }
 
 // Pause State
@@ -123,7 +422,7 @@ impl event::EventHandler for PauseState {
 }
 
 impl InnerState for PauseState {
-    fn transition(&self, _font: graphics::Font, keycode: event::KeyCode) -> Transition {
+    fn transition(&self, keycode: event::KeyCode) -> Transition {
         if keycode == event::KeyCode::Space {
             return Transition::Pop;
         }
@@ -133,15 +432,19 @@ impl InnerState for PauseState {
 
 // End State (usually game over since now we have infinite levels)
 struct EndState {
+    assets: Rc<Assets>,
+    options: Rc<Options>,
     title_text: graphics::Text,
     restart_text: graphics::Text,
 }
 
 impl EndState {
-    pub fn new(message: &str, font: graphics::Font) -> Self {
-        let title_text = graphics::Text::new((message, font, 72.0));
-        let restart_text = graphics::Text::new(("Press <SPACE> to restart", font, 25.0));
+    pub fn new(message: &str, assets: Rc<Assets>, options: Rc<Options>) -> Self {
+        let title_text = graphics::Text::new((message, assets.font, 72.0));
+        let restart_text = graphics::Text::new(("Press <SPACE> to restart", assets.font, 25.0));
         Self {
+            assets,
+            options,
             title_text,
             restart_text,
         }
@@ -173,61 +476,205 @@ impl event::EventHandler for EndState {
 }
 
 impl InnerState for EndState {
-    fn transition(&self, font: graphics::Font, keycode: event::KeyCode) -> Transition {
+    fn transition(&self, keycode: event::KeyCode) -> Transition {
         if keycode == event::KeyCode::Space {
-            return Transition::Replace(Box::new(PlayState::new(font, 0, 0)));
+            let intro = IntroState::new(
+                self.assets.clone(),
+                self.options.clone(),
+                self.options.level,
+                0,
+                LIVES,
+                None,
+            );
+            return Transition::Animate(Box::new(intro), Effect::Fade);
         }
         Transition::None
     }
+
+    fn music(&self) -> Option<Music> {
+        Some(Music::GameOver)
+    }
 }
 
 // Main state
 struct MainState {
-    font: graphics::Font,
     inner_state: Vec<Box<dyn InnerState>>,
+    options: Rc<Options>,         // command line options
+    replay: Option<Replay>,       // replay to play back once assets are loaded
+    audio: AudioManager,          // sounds and music
+    assets: Option<Rc<Assets>>,   // all assets, once loaded
+    watcher: Option<Watcher>,     // watches level and theme files, in development mode
+    reload_error: Option<String>, // error while reloading levels or themes
+    animation: Option<Animation>, // running transition animation
+    debug: DebugOverlay,          // debug information, on top of everything
+    canvases: (graphics::Canvas, graphics::Canvas), // off screen rendering of animated states
 }
 
 impl MainState {
-    fn new(ctx: &mut Context) -> GameResult<MainState> {
-        let font = graphics::Font::new(ctx, "/PixelEmulator-xq08.ttf")?;
+    fn new(
+        ctx: &mut Context,
+        options: Options,
+        audio_enabled: bool,
+        watcher: Option<Watcher>,
+        replay: Option<Replay>,
+    ) -> GameResult<MainState> {
+        let loader = Loader::new(ctx, options.levels.clone())?;
+        // the game is drawn at its own size whatever the window size
+        graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, WIDTH, HEIGHT))?;
+        let canvases = (
+            graphics::Canvas::new(ctx, WIDTH as u16, HEIGHT as u16, conf::NumSamples::One)?,
+            graphics::Canvas::new(ctx, WIDTH as u16, HEIGHT as u16, conf::NumSamples::One)?,
+        );
+        let mut audio = AudioManager::new(audio_enabled);
+        audio.muted = options.mute;
+        let debug = DebugOverlay::new(options.debug);
 
         let s = MainState {
-            font,
-            inner_state: vec![Box::new(StartState::new(font))],
+            inner_state: vec![Box::new(LoadingState::new(loader))],
+            options: Rc::new(options),
+            replay,
+            audio,
+            assets: None,
+            watcher,
+            reload_error: None,
+            animation: None,
+            debug,
+            canvases,
         };
         Ok(s)
     }
-}
 
-impl event::EventHandler for MainState {
-    fn update(&mut self, ctx: &mut Context) -> GameResult {
-        // update inner state
-        self.inner_state.last_mut().unwrap().update(ctx)?;
-        // check for transition
-        let tr = self
-            .inner_state
-            .last_mut()
-            .unwrap()
-            .state_transition(self.font);
-        // apply transition
+    // apply a transition, returns false if there was nothing to do
+    fn apply_transition(&mut self, ctx: &mut Context, tr: Transition) -> bool {
         match tr {
             Transition::Replace(ns) => {
                 self.inner_state.pop();
                 self.inner_state.push(ns);
             }
+            Transition::Animate(ns, effect) => {
+                let from = self.inner_state.pop().unwrap();
+                self.inner_state.push(ns);
+                self.animation = Some(Animation::new(from, effect));
+            }
+            Transition::Loaded(assets) => {
+                self.audio.load(ctx, &assets);
+                self.assets = Some(assets.clone());
+                let from = self.inner_state.pop().unwrap();
+                let ns: Box<dyn InnerState> = match self.replay.take() {
+                    Some(replay) => Box::new(PlayState::replay(assets, self.options.clone(), replay)),
+                    None => Box::new(StartState::new(assets, self.options.clone())),
+                };
+                self.inner_state.push(ns);
+                self.animation = Some(Animation::new(from, Effect::Fade));
+            }
             Transition::Pop => {
                 self.inner_state.pop();
             }
             Transition::Push(ns) => {
                 self.inner_state.push(ns);
             }
-            _ => {}
+            Transition::None => return false,
+        }
+        true
+    }
+
+    // run the commands typed in the console on the state below it
+    fn run_commands(&mut self) {
+        let n = self.inner_state.len();
+        let commands = self.inner_state[n - 1].take_commands();
+        for command in commands {
+            let result = match n.checked_sub(2).and_then(|ix| self.inner_state[ix].commands()) {
+                Some(target) => target.run(&command),
+                None => Err("nothing to run commands on".to_owned()),
+            };
+            self.inner_state[n - 1].command_result(result);
+        }
+    }
+
+    // reload changed level and theme files, and restart the states using them
+    fn hot_reload(&mut self) {
+        let (watcher, assets) = match (&mut self.watcher, &self.assets) {
+            (Some(watcher), Some(assets)) => (watcher, assets),
+            _ => return,
+        };
+        let changed = watcher.poll();
+        if changed.is_empty() {
+            return;
+        }
+        // reload every changed file, even after an error, since they will not be reported again
+        let mut error = None;
+        for path in changed {
+            if let Err(e) = watcher.reload(assets, &path) {
+                println!("{}", e);
+                error = error.or(Some(e));
+            }
+        }
+        self.reload_error = error;
+        if self.reload_error.is_some() {
+            return;
+        }
+        for state in self.inner_state.iter_mut() {
+            if let Some(ns) = state.reload() {
+                *state = ns;
+            }
+        }
+    }
+
+    // a single update of the game, at a fixed rate
+    fn tick(&mut self, ctx: &mut Context) -> GameResult {
+        // development mode: pick up level and theme changes
+        self.hot_reload();
+        // follow the music of the current state
+        if let Some(music) = self.inner_state.last().unwrap().music() {
+            self.audio.play_music(music);
+        }
+        // states are frozen while an animation runs
+        if let Some(anim) = &self.animation {
+            if !anim.finished() {
+                return Ok(());
+            }
+            self.animation = None;
+        }
+        // update inner state
+        self.inner_state.last_mut().unwrap().update(ctx)?;
+        for sfx in self.inner_state.last_mut().unwrap().take_sounds() {
+            self.audio.play_sfx(sfx);
+        }
+        // check for transition
+        let tr = self.inner_state.last_mut().unwrap().state_transition();
+        // apply transition
+        self.apply_transition(ctx, tr);
+        Ok(())
+    }
+}
+
+impl event::EventHandler for MainState {
+    // states are updated at a fixed rate, so replays play back exactly as recorded
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        let mut ticks = 0;
+        while timer::check_update_time(ctx, TICKS_PER_SECOND) {
+            ticks += 1;
+            // after a long pause, skip the missed ticks instead of playing them all at once
+            if ticks <= MAX_TICKS_PER_UPDATE {
+                let start = Instant::now();
+                self.tick(ctx)?;
+                self.debug.set_tick_time(start.elapsed());
+            }
         }
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        self.inner_state.last_mut().unwrap().draw(ctx)?;
+        let current = self.inner_state.last_mut().unwrap();
+        if let Some(anim) = &mut self.animation {
+            anim.draw(ctx, current.as_mut(), &self.canvases)?;
+        } else {
+            current.draw(ctx)?;
+        }
+        if let (Some(error), Some(assets)) = (&self.reload_error, &self.assets) {
+            reload::draw_error(ctx, assets.font, error)?;
+        }
+        self.debug.draw(ctx, &self.inner_state)?;
 
         graphics::present(ctx)?;
         Ok(())
@@ -240,37 +687,74 @@ impl event::EventHandler for MainState {
         keymod: event::KeyMods,
         repeat: bool,
     ) {
+        // audio and debug settings work in any state, unless typing
+        let typing = self.inner_state.last().unwrap().takes_text();
+        match keycode {
+            event::KeyCode::F3 => return self.debug.toggle(),
+            _ if typing => (),
+            event::KeyCode::M => return self.audio.toggle_mute(),
+            event::KeyCode::F5 => return self.audio.change_music_volume(-VOLUME_STEP),
+            event::KeyCode::F6 => return self.audio.change_music_volume(VOLUME_STEP),
+            event::KeyCode::F7 => return self.audio.change_sfx_volume(-VOLUME_STEP),
+            event::KeyCode::F8 => return self.audio.change_sfx_volume(VOLUME_STEP),
+            _ => (),
+        }
+        // ignore keys until the animation is over
+        if self.animation.is_some() {
+            return;
+        }
+        // development mode: open the console on states accepting commands
+        if self.options.dev && keycode == event::KeyCode::Grave {
+            if let Some(state) = self.inner_state.last_mut() {
+                if state.commands().is_some() {
+                    self.inner_state.push(Box::new(ConsoleState::new()));
+                    return;
+                }
+            }
+        }
         // check for transition
-        let tr = self
-            .inner_state
-            .last_mut()
-            .unwrap()
-            .transition(self.font, keycode);
+        let tr = self.inner_state.last_mut().unwrap().transition(keycode);
         // apply transition if any
-        match tr {
-            Transition::Replace(ns) => {
-                self.inner_state.pop();
-                self.inner_state.push(ns);
-            }
-            Transition::Pop => {
-                self.inner_state.pop();
-            }
-            Transition::Push(ns) => {
-                self.inner_state.push(ns);
-            }
-            // no transition: notify state of key down event
-            _ => {
-                self.inner_state
-                    .last_mut()
-                    .unwrap()
-                    .key_down_event(ctx, keycode, keymod, repeat);
-            }
+        // no transition: notify state of key down event
+        if !self.apply_transition(ctx, tr) {
+            self.inner_state
+                .last_mut()
+                .unwrap()
+                .key_down_event(ctx, keycode, keymod, repeat);
+            self.run_commands();
+        }
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, dx: f32, dy: f32) {
+        if self.animation.is_none() {
+            // window pixels to playfield coordinates
+            let (w, h) = graphics::drawable_size(ctx);
+            let (sx, sy) = (WIDTH / w, HEIGHT / h);
+            self.inner_state
+                .last_mut()
+                .unwrap()
+                .mouse_motion_event(ctx, x * sx, y * sy, dx * sx, dy * sy);
+        }
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) {
+        if self.animation.is_none() {
+            self.inner_state
+                .last_mut()
+                .unwrap()
+                .text_input_event(ctx, character);
         }
     }
 }
 
 // Game app entry point
 pub fn main() -> GameResult {
+    let mut options = Options::from_args();
+    // always play with a known seed, so the game can be played again
+    let seed = options.seed.unwrap_or_else(rand::random);
+    options.seed = Some(seed);
+    println!("Seed: {}", seed);
+
     let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
         let mut path = path::PathBuf::from(manifest_dir);
         path.push("resources");
@@ -278,6 +762,40 @@ pub fn main() -> GameResult {
     } else {
         path::PathBuf::from("./resources")
     };
+    let levels_dir = options
+        .levels
+        .clone()
+        .unwrap_or_else(|| resource_dir.join("levels"));
+
+    // no window needed to measure the simulation
+    if let Some(games) = options.benchmark {
+        match level::read_levels(&levels_dir) {
+            Ok(defs) => bench::run(&options, &defs, games),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    // read the replay before opening the window, to report errors early
+    let replay = match &options.replay {
+        Some(path) => match Replay::load(path) {
+            Ok(replay) => Some(replay),
+            Err(e) => {
+                eprintln!("Cannot read replay {}", e);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // development mode reloads levels and themes when they change on disk
+    options.dev = options.dev || env::var("BLOCKS_DEV").is_ok();
+    let watcher = if options.dev {
+        Some(Watcher::new(&resource_dir, options.levels.as_deref()))
+    } else {
+        None
+    };
     let ws = conf::WindowSetup {
         title: "Blocks".to_owned(),
         samples: conf::NumSamples::Zero,
@@ -285,48 +803,49 @@ pub fn main() -> GameResult {
         icon: "".to_owned(),
         srgb: true,
     };
-    let wm = conf::WindowMode::default().dimensions(WIDTH, HEIGHT);
+    let (width, height) = match options.resolution {
+        Some(r) => (r.width, r.height),
+        None => (WIDTH, HEIGHT),
+    };
+    let mut wm = conf::WindowMode::default().dimensions(width, height);
+    if options.fullscreen {
+        wm = wm.fullscreen_type(conf::FullscreenType::Desktop);
+    }
 
-    let cb = ggez::ContextBuilder::new("blocks", "JP Moresmau")
-        .window_setup(ws)
-        .window_mode(wm)
-        .add_resource_path(resource_dir);
-    let (ctx, event_loop) = &mut cb.build()?;
+    let cb = |audio: bool| {
+        ggez::ContextBuilder::new("blocks", "JP Moresmau")
+            .window_setup(ws.clone())
+            .window_mode(wm)
+            .modules(conf::ModuleConf {
+                gamepad: false,
+                audio,
+            })
+            .add_resource_path(resource_dir.clone())
+    };
+    // no audio device is not a reason not to play
+    let (mut built, mut audio_enabled) = (cb(true).build(), true);
+    if let Err(e) = &built {
+        println!("Cannot initialize audio, playing without sound:{}", e);
+        built = cb(false).build();
+        audio_enabled = false;
+    }
+    let (ctx, event_loop) = &mut built?;
 
-    let state = &mut MainState::new(ctx)?;
+    let state = &mut MainState::new(ctx, options, audio_enabled, watcher, replay)?;
     event::run(ctx, event_loop, state)
 }
 
 const UPDATES_PER_SECOND: f32 = 2.0;
 const MILLIS_PER_UPDATE: u64 = (1.0 / UPDATES_PER_SECOND * 1000.0) as u64;
 
-pub const WIDTH: f32 = 800.0;
-pub const HEIGHT: f32 = 450.0;
-
-pub const LIGHTGRAY: graphics::Color = graphics::Color {
-    r: 0.83,
-    g: 0.83,
-    b: 0.83,
-    a: 1.0,
-};
-
-pub const GRAY: graphics::Color = graphics::Color {
-    r: 0.5,
-    g: 0.5,
-    b: 0.5,
-    a: 1.0,
-};
-
-pub const DARKGRAY: graphics::Color = graphics::Color {
-    r: 0.33,
-    g: 0.33,
-    b: 0.33,
-    a: 1.0,
-};
-
-pub const RED: graphics::Color = graphics::Color {
-    r: 0.83,
-    g: 0.13,
-    b: 0.18,
-    a: 1.0,
-};
+// Most ticks played in a single update, when the game falls behind
+const MAX_TICKS_PER_UPDATE: u32 = 5;
+
+// Volume change per key press
+const VOLUME_STEP: f32 = 0.1;
+
+// Idle time on the title screen before the demo starts
+const ATTRACT_SECS: u64 = 10;
+
+// How long the level intro card is shown
+const MILLIS_PER_INTRO: u64 = 1500;
@@ -5,194 +5,321 @@ use ggez::event;
 use ggez::graphics;
 use ggez::nalgebra as na;
 use ggez::{Context, GameResult};
-use ggez::audio::{Source,SoundSource};
+use std::rc::Rc;
 
 use super::*;
+use crate::cli::Options;
+use crate::console::{Command, Commands};
+use crate::boss::{Boss, SHOT_RADIUS};
+use crate::controller::{Autopilot, Keyboard, PaddleController};
+use crate::enemy::ENEMY_RADIUS;
+use crate::fixture::{Fixture, PORTAL_RADIUS};
 use crate::level::*;
-
-// Different modes this play screen can be in
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
-enum PlayMode {
-    Pending,
-    Running,
-    Lost,
-    Won,
-}
-
-// Bounce direction on a block
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
-enum Bounce {
-    Bottom,
-    Top,
-    Left,
-    Right,
-    None,
-}
+use crate::projectile::{Kind, PowerUp};
+use crate::replay::Replay;
+use crate::scoring::*;
+use crate::sim::*;
+use crate::summary::LevelCompleteState;
+use crate::theme::Theme;
+use crate::time_attack::{format_ticks, TimeAttack};
 
 // Full play state
 pub struct PlayState {
-    help_text: graphics::Text,    // help text
-    font: graphics::Font,         // font for text
-    block_sound: Option<Source>,  // sound when hitting a block
-    paddle_sound: Option<Source>, // sound when hitting the paddle
-    mode: PlayMode,  // current mode
-    level: Level, // level definition
-    paddle: f32, // paddle x position
-    ball: (f32, f32), // ball position
-    speed: (f32, f32), // paddle speed (left/right)
-    ball_speed: (f32, f32), // ball speed vector
-    score: u32, // user score
+    help_text: graphics::Text,              // help text
+    assets: Rc<Assets>,                     // fonts and levels
+    options: Rc<Options>,                   // command line options
+    sounds: Vec<Sfx>,                       // sounds to play
+    sim: Sim,                               // the game itself
+    theme: Theme,                           // colors
+    popups: Vec<Popup>,                     // points scored recently
+    controller: Box<dyn PaddleController>,  // moves the paddle
+    demo: bool,                             // not played by the player, any key ends it
+    max_ticks: Option<u32>,                 // length of a demo
+    recording: Option<Replay>,              // inputs recorded, when asked on the command line
+    pub attack: Option<TimeAttack>,         // time attack run, if playing one
 }
 
 impl PlayState {
     // new play state, using level at given index
-    pub fn new(font: graphics::Font, index: u32, score: u32) -> Self {
-        let level = next_level(index);
-        let speed = level.ball_speed;
-        let help_text = graphics::Text::new(("Press <SPACE> to launch the ball", font, 18.0));
-        
+    pub fn new(
+        assets: Rc<Assets>,
+        options: Rc<Options>,
+        index: u32,
+        score: u32,
+        lives: u32,
+    ) -> Self {
+        let seed = options.level_seed(index);
+        let controller: Box<dyn PaddleController> = if options.autopilot {
+            Box::new(Autopilot::new(seed))
+        } else {
+            Box::new(Keyboard::default())
+        };
+        let mut state =
+            PlayState::with_controller(assets, options, index, score, lives, seed, controller);
+        if state.options.record.is_some() {
+            state.recording = Some(Replay::new(index, score, lives, seed));
+        }
+        state
+    }
+
+    // play back a recorded level
+    pub fn replay(assets: Rc<Assets>, options: Rc<Options>, replay: Replay) -> Self {
+        let (index, score, lives, seed) = (replay.index, replay.score, replay.lives, replay.seed);
+        let mut state = PlayState::with_controller(
+            assets,
+            options,
+            index,
+            score,
+            lives,
+            seed,
+            Box::new(replay),
+        );
+        state.help_text = graphics::Text::new(("Replay", state.assets.font, 18.0));
+        state.demo = true;
+        state
+    }
+
+    // attract mode: the computer plays a random level for a while
+    pub fn demo(assets: Rc<Assets>, options: Rc<Options>) -> Self {
+        let seed = rand::random();
+        let index = (seed % assets.levels().len() as u64) as u32;
+        let controller = Box::new(Autopilot::new(seed));
+        let mut state =
+            PlayState::with_controller(assets, options, index, 0, LIVES, seed, controller);
+        state.help_text = graphics::Text::new(("DEMO - Press any key", state.assets.font, 18.0));
+        state.demo = true;
+        state.max_ticks = Some(DEMO_SECS * TICKS_PER_SECOND);
+        state
+    }
+
+    fn with_controller(
+        assets: Rc<Assets>,
+        options: Rc<Options>,
+        index: u32,
+        score: u32,
+        lives: u32,
+        seed: u64,
+        controller: Box<dyn PaddleController>,
+    ) -> Self {
+        let level = next_level(&assets.levels(), index);
+        let theme = assets.theme(&level.theme);
+        let help_text =
+            graphics::Text::new(("Press <SPACE> to launch the ball", assets.font, 18.0));
+
         Self {
             help_text,
-            font,
-            block_sound: Option::None,
-            paddle_sound: Option::None,
-            mode: PlayMode::Pending,
-            level,
-            paddle: WIDTH / 2.0,
-            ball: (WIDTH / 2.0, 390.0),
-            speed: (1.0, 1.0),
-            ball_speed: (0.0, -speed),
-            score,
+            assets,
+            options,
+            sounds: Vec::new(),
+            sim: Sim::new(level, score, lives, seed),
+            theme,
+            popups: Vec::new(),
+            controller,
+            demo: false,
+            max_ticks: None,
+            recording: None,
+            attack: None,
         }
     }
 
+    // back to the title screen, at the end of a demo
+    fn title(&self) -> Transition {
+        Transition::Animate(
+            Box::new(StartState::new(self.assets.clone(), self.options.clone())),
+            Effect::Fade,
+        )
+    }
+
     // draw a single block
-    fn draw_block(mb: &mut graphics::MeshBuilder, block: &Block) {
-        mb.rectangle(
-            graphics::DrawMode::Fill(graphics::FillOptions::default()),
-            block.rect,
-            block.fill,
-        );
-        mb.rectangle(
-            graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
-            block.rect,
-            block.stroke,
-        );
+    fn draw_block(mb: &mut graphics::MeshBuilder, block: &Block, theme: &Theme) -> GameResult {
+        match block.shape {
+            Shape::Rect => {
+                mb.rectangle(
+                    graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                    block.rect,
+                    theme.fill(block.kind),
+                );
+                mb.rectangle(
+                    graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
+                    block.rect,
+                    theme.stroke,
+                );
+            }
+            _ => {
+                let points: Vec<na::Point2<f32>> =
+                    block.outline().into_iter().map(|(x, y)| na::Point2::new(x, y)).collect();
+                mb.polygon(
+                    graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                    &points,
+                    theme.fill(block.kind),
+                )?;
+                mb.polygon(
+                    graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
+                    &points,
+                    theme.stroke,
+                )?;
+            }
+        }
+        Ok(())
     }
 
-    // calculate collision of ball with walls, blocks and paddle
-    fn collision(&mut self) {
-        let (bx, by) = self.ball;
-        let ball_speed = self.level.ball_speed;
-        // vertical wall collision
-        if bx - BALL_RADIUS <= 0.0 || bx + BALL_RADIUS >= WIDTH {
-            self.ball_speed.0 = -self.ball_speed.0;
-        // top wall collision
-        } else if by - BALL_RADIUS <= 0.0 {
-            self.ball_speed.1 = -self.ball_speed.1;
-        // ball falls below paddle, lost!
-        } else if by + BALL_RADIUS > 420.0 {
-            self.mode = PlayMode::Lost;
-        // paddle collision
-        } else if self.ball_speed.1 > 0.0
-            && by + BALL_RADIUS > 400.0
-            && bx + BALL_RADIUS >= self.paddle - PADDLE_WIDTH / 2.0 - 10.0
-            && bx - BALL_RADIUS <= self.paddle + PADDLE_WIDTH / 2.0 + 10.0
-        {
-            if let Some(bs) = &mut self.paddle_sound {
-                bs.play().unwrap_or_else(|e| println!("Cannot play sound:{}",e));
+    // draw a bumper, one-way wall, portal or speed zone
+    fn draw_fixture(mb: &mut graphics::MeshBuilder, fixture: &Fixture, theme: &Theme) {
+        match *fixture {
+            Fixture::Bumper { center, radius } => {
+                mb.circle(
+                    graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                    na::Point2::new(center.0, center.1),
+                    radius,
+                    0.1,
+                    theme.paddle,
+                );
+                mb.circle(
+                    graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
+                    na::Point2::new(center.0, center.1),
+                    radius,
+                    0.1,
+                    theme.outline,
+                );
             }
-            // calculate how to adapt the bounce according to the position of contact
-            let ratio = (bx - self.paddle) / 20.0;
-            self.ball_speed.1 = -self.ball_speed.1;
-            if ratio != 0.0 {
-                self.ball_speed.0 = (self.ball_speed.0 + ratio)
-                    .min(ball_speed - 0.1)
-                    .max(-ball_speed + 0.1);
-                self.ball_speed.1 =
-                    -(ball_speed * ball_speed - self.ball_speed.0 * self.ball_speed.0).sqrt();
+            Fixture::OneWay { rect, .. } => {
+                mb.rectangle(
+                    graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                    rect,
+                    theme.outline,
+                );
             }
-        } else {
-            let (bsx, bsy) = self.ball_speed;
-            let mut bounce = Bounce::None;
-            let mut sc = 0;
-            // approximate ball by rectangle
-            let ball_rect = graphics::Rect::new(
-                bx - BALL_RADIUS,
-                by - BALL_RADIUS,
-                BALL_RADIUS * 2.0,
-                BALL_RADIUS * 2.0,
-            );
-            // calculate hit blocks
-            self.level.blocks.retain(|b| {
-                if b.rect.overlaps(&ball_rect) {
-                    if bsy < 0.0 && by > b.rect.y + b.rect.h {
-                        bounce = Bounce::Bottom;
-                    } else if bsy > 0.0 && by < b.rect.y {
-                        bounce = Bounce::Top;
-                    } else if bsx < 0.0 && bx > b.rect.x + b.rect.w {
-                        bounce = Bounce::Right;
-                    } else {
-                        bounce = Bounce::Left;
-                    }
-                    sc += 1;
-                    return false;
-                }
-                true
-            });
-            if sc>0{
-                if let Some(bs) = &mut self.block_sound {
-                    bs.play().unwrap_or_else(|e| println!("Cannot play sound:{}",e));
+            Fixture::Portal { ends } => {
+                for end in ends.iter() {
+                    mb.circle(
+                        graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
+                        na::Point2::new(end.0, end.1),
+                        PORTAL_RADIUS,
+                        0.1,
+                        theme.ball,
+                    );
                 }
             }
-            match bounce {
-                Bounce::Bottom => {
-                    self.ball_speed.1 = -self.ball_speed.1;
-                }
-                Bounce::Top => {
-                    self.ball_speed.1 = -self.ball_speed.1;
-                }
-                Bounce::Left => {
-                    self.ball_speed.0 = -self.ball_speed.0;
-                }
-                Bounce::Right => {
-                    self.ball_speed.0 = -self.ball_speed.0;
-                }
-                _ => {}
+            // faster zones look like enemies, slower ones like the paddle
+            Fixture::SpeedZone { rect, factor } => {
+                let mut color = if factor > 1.0 { theme.enemy } else { theme.paddle };
+                color.a = SPEED_ZONE_ALPHA;
+                mb.rectangle(graphics::DrawMode::Fill(graphics::FillOptions::default()), rect, color);
             }
-            self.score += sc;
-            if self.level.blocks.is_empty() {
-                self.mode = PlayMode::Won;
+        }
+    }
+
+    // draw the boss with its weak spots, and its shots
+    fn draw_boss(mb: &mut graphics::MeshBuilder, boss: &Boss, theme: &Theme) {
+        if !boss.defeated() {
+            mb.rectangle(
+                graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                boss.rect,
+                theme.enemy,
+            );
+            mb.rectangle(
+                graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
+                boss.rect,
+                theme.stroke,
+            );
+            for w in boss.weak_spots().iter() {
+                mb.rectangle(
+                    graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                    *w,
+                    theme.ball,
+                );
             }
         }
+        for s in boss.shots.iter() {
+            mb.circle(
+                graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                na::Point2::new(s.pos.0, s.pos.1),
+                SHOT_RADIUS,
+                0.1,
+                theme.enemy,
+            );
+        }
     }
 }
 
 impl event::EventHandler for PlayState {
-    fn update(&mut self, ctx: &mut Context) -> GameResult {
-        // load sounds now that we have a context to do so
-        if self.block_sound.is_none(){
-            self.block_sound = Option::Some(Source::new(ctx, "/321585__waxxman__bip.mp3")?);
-            self.paddle_sound = Option::Some(Source::new(ctx, "/399196__spiceprogram__perc-bip.wav")?);
-        }
-        // update ball and calculate collisions
-        if self.mode == PlayMode::Running {
-            self.ball.0 += self.ball_speed.0;
-            self.ball.1 += self.ball_speed.1;
-            self.collision();
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        for input in self.controller.inputs(&self.sim) {
+            if let Some(recording) = &mut self.recording {
+                recording.record(self.sim.ticks, input);
+            }
+            self.sim.input(input);
         }
+        self.sim.tick();
+        for event in self.sim.take_events() {
+            match event {
+                Event::Block {
+                    block,
+                    combo,
+                    points,
+                } => {
+                    let center = (
+                        block.rect.x + block.rect.w / 2.0,
+                        block.rect.y + block.rect.h / 2.0,
+                    );
+                    self.popups.push(Popup::new(self.assets.font, points, center));
+                    self.sounds.push(Sfx::Block(combo));
+                }
+                Event::Paddle => self.sounds.push(Sfx::Paddle),
+                Event::Enemy { pos, points } | Event::Boss { pos, points } => {
+                    self.popups.push(Popup::new(self.assets.font, points, pos));
+                    self.sounds.push(Sfx::Block(1));
+                }
+                Event::PowerUp(_) => self.sounds.push(Sfx::PowerUp),
+                Event::Fire => self.sounds.push(Sfx::Laser),
+                Event::WarpOpen => self.sounds.push(Sfx::PowerUp),
+                Event::Fixture => self.sounds.push(Sfx::Paddle),
+            }
+        }
+        // the level is over: save what was recorded
+        if self.sim.finished() {
+            if let (Some(recording), Some(path)) = (self.recording.take(), &self.options.record) {
+                match recording.save(path) {
+                    Ok(()) => println!("Recorded replay to {}", path.display()),
+                    Err(e) => println!("Cannot record replay: {}", e),
+                }
+            }
+        }
+        for p in self.popups.iter_mut() {
+            p.update();
+        }
+        self.popups.retain(|p| !p.expired());
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        graphics::clear(ctx, LIGHTGRAY);
+        graphics::clear(ctx, self.theme.background);
+        // optional background image
+        if let Some(background) = self.assets.texture("background") {
+            graphics::draw(ctx, background, graphics::DrawParam::new())?;
+        }
         let mb = &mut graphics::MeshBuilder::new();
-        for b in self.level.blocks.iter() {
-            PlayState::draw_block(mb, b);
+        for f in self.sim.level.fixtures.iter() {
+            PlayState::draw_fixture(mb, f, &self.theme);
+        }
+        for b in self.sim.level.blocks.iter().filter(|b| b.kind != BlockKind::Hidden) {
+            PlayState::draw_block(mb, b, &self.theme)?;
+        }
+        // draw the open warp exit, in the wall next to the paddle
+        if let (true, Some(warp)) = (self.sim.warp_open, self.sim.level.warp) {
+            let x = match warp.side {
+                Direction::Left => 0.0,
+                Direction::Right => WIDTH - WARP_GATE_WIDTH,
+            };
+            mb.rectangle(
+                graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                graphics::Rect::new(x, 390.0, WARP_GATE_WIDTH, PADDLE_HEIGHT + 20.0),
+                self.theme.ball,
+            );
         }
         // draw the paddle
         let rect = graphics::Rect::new(
-            self.paddle - PADDLE_INNERWIDTH / 2.0,
+            self.sim.paddle - PADDLE_INNERWIDTH / 2.0,
             400.0,
             PADDLE_INNERWIDTH,
             PADDLE_HEIGHT,
@@ -203,12 +330,12 @@ impl event::EventHandler for PlayState {
             na::Point2::new(rect.x - 10.0, rect.y + PADDLE_HEIGHT / 2.0),
             10.0,
             0.1,
-            DARKGRAY,
+            self.theme.paddle,
         );
         mb.rectangle(
             graphics::DrawMode::Fill(graphics::FillOptions::default()),
             graphics::Rect::new(rect.x - 10.0, rect.y, 10.0, PADDLE_HEIGHT),
-            DARKGRAY,
+            self.theme.paddle,
         );
 
         mb.circle(
@@ -216,56 +343,172 @@ impl event::EventHandler for PlayState {
             na::Point2::new(rect.x + PADDLE_WIDTH - 10.0, rect.y + PADDLE_HEIGHT / 2.0),
             10.0,
             0.1,
-            DARKGRAY,
+            self.theme.paddle,
         );
         mb.rectangle(
             graphics::DrawMode::Fill(graphics::FillOptions::default()),
             graphics::Rect::new(rect.x + PADDLE_WIDTH - 20.0, rect.y, 10.0, PADDLE_HEIGHT),
-            DARKGRAY,
+            self.theme.paddle,
         );
 
         mb.rectangle(
             graphics::DrawMode::Fill(graphics::FillOptions::default()),
             rect,
-            LIGHTGRAY,
+            self.theme.paddle_inner,
         );
         mb.rectangle(
             graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
             graphics::Rect::new(rect.x, rect.y + 1.0, rect.w, rect.h - 1.0),
-            DARKGRAY,
+            self.theme.outline,
         );
 
+        // draw the laser cannons on the paddle ends
+        if self.sim.laser_ticks > 0 {
+            for x in [rect.x - 12.0, rect.x + PADDLE_WIDTH - 12.0].iter() {
+                mb.rectangle(
+                    graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                    graphics::Rect::new(*x, rect.y - 6.0, 4.0, 6.0),
+                    self.theme.paddle,
+                );
+            }
+        }
+
+        // draw laser shots and capsules
+        for p in self.sim.projectiles.iter() {
+            let color = match p.kind {
+                Kind::Laser => self.theme.ball,
+                Kind::Capsule(PowerUp::Laser) => self.theme.enemy,
+                Kind::Capsule(PowerUp::Catch) => self.theme.paddle,
+            };
+            mb.rectangle(
+                graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                p.rect,
+                color,
+            );
+        }
+
+        // draw the boss and its shots
+        if let Some(boss) = &self.sim.boss {
+            PlayState::draw_boss(mb, boss, &self.theme);
+        }
+
+        // draw the enemies
+        for e in self.sim.enemies.iter() {
+            let center = na::Point2::new(e.pos.0, e.pos.1);
+            mb.circle(
+                graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                center,
+                ENEMY_RADIUS,
+                0.1,
+                self.theme.enemy,
+            );
+            mb.circle(
+                graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
+                center,
+                ENEMY_RADIUS / 2.0,
+                0.1,
+                self.theme.outline,
+            );
+        }
+
+        // draw where the ball will be launched, all the way with the preview assist
+        if self.sim.mode == PlayMode::Pending && !self.demo {
+            let path = self.sim.launch_path();
+            let dots = if self.options.preview {
+                path.len()
+            } else {
+                AIM_DOTS
+            };
+            for (x, y) in path.into_iter().take(dots) {
+                mb.circle(
+                    graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                    na::Point2::new(x, y),
+                    2.0,
+                    0.1,
+                    self.theme.outline,
+                );
+            }
+        }
+
         // draw the ball
-        if self.mode != PlayMode::Lost {
+        if self.sim.mode != PlayMode::Lost {
             mb.circle(
                 graphics::DrawMode::Fill(graphics::FillOptions::default()),
-                na::Point2::new(self.ball.0, self.ball.1),
+                na::Point2::new(self.sim.ball.0, self.sim.ball.1),
                 BALL_RADIUS,
                 0.1,
-                RED,
+                self.theme.ball,
             );
             mb.circle(
                 graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
-                na::Point2::new(self.ball.0, self.ball.1),
+                na::Point2::new(self.sim.ball.0, self.sim.ball.1),
                 BALL_RADIUS,
                 0.1,
-                DARKGRAY,
+                self.theme.outline,
+            );
+        }
+
+        // draw the boss health
+        if let Some(boss) = &self.sim.boss {
+            let x = WIDTH / 2.0 - HEALTH_BAR_WIDTH / 2.0;
+            let bar = graphics::Rect::new(x, 10.0, HEALTH_BAR_WIDTH, 10.0);
+            let health = boss.health as f32 / boss.max_health as f32;
+            mb.rectangle(
+                graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                graphics::Rect::new(bar.x, bar.y, bar.w * health, bar.h),
+                self.theme.ball,
+            );
+            mb.rectangle(
+                graphics::DrawMode::Stroke(graphics::StrokeOptions::default()),
+                bar,
+                self.theme.outline,
             );
         }
 
         let m = mb.build(ctx)?;
         graphics::draw(ctx, &m, graphics::DrawParam::new())?;
 
-        // draw help text
-        if self.mode == PlayMode::Pending {
+        // draw score popups
+        for p in self.popups.iter() {
+            p.draw(ctx)?;
+        }
+
+        // draw help text, always shown in demos
+        if self.sim.mode == PlayMode::Pending || self.sim.caught.is_some() || self.demo {
             let (w, _h) = self.help_text.dimensions(ctx);
             let dest_point = na::Point2::new(WIDTH / 2.0 - (w as f32 / 2.0), 426.0);
-            graphics::draw(ctx, &self.help_text, (dest_point, DARKGRAY))?;
+            graphics::draw(ctx, &self.help_text, (dest_point, self.theme.text))?;
         }
 
         // draw score
-        let score_text = graphics::Text::new((format!("Score: {}",self.score), self.font, 18.0));
-        graphics::draw(ctx, &score_text, (na::Point2::new(5.0,426.0), DARKGRAY))?;
+        let score_text = graphics::Text::new((format!("Score: {}",self.sim.score), self.assets.font, 18.0));
+        graphics::draw(ctx, &score_text, (na::Point2::new(5.0,426.0), self.theme.text))?;
+
+        // draw lives left
+        let lives_text = graphics::Text::new((format!("Lives: {}", self.sim.lives), self.assets.font, 18.0));
+        let (w, _h) = lives_text.dimensions(ctx);
+        graphics::draw(
+            ctx,
+            &lives_text,
+            (na::Point2::new(WIDTH - w as f32 - 5.0, 426.0), self.theme.text),
+        )?;
+
+        // draw the time attack timer, red once behind the best run
+        if let Some(attack) = &self.attack {
+            let total = attack.ticks + self.sim.ticks;
+            let mut timer = format_ticks(total);
+            let mut color = self.theme.text;
+            if let Some(best) = attack.best_at(self.sim.level.index) {
+                timer = format!("{}  PB {}", timer, format_ticks(best));
+                if total > best {
+                    color = RED;
+                }
+            }
+            let timer_text = graphics::Text::new((timer, self.assets.font, 18.0));
+            let (tw, _h) = timer_text.dimensions(ctx);
+            let x = WIDTH - w as f32 - tw as f32 - 25.0;
+            graphics::draw(ctx, &timer_text, (na::Point2::new(x, 426.0), color))?;
+        }
         Ok(())
     }
 
@@ -276,85 +519,179 @@ impl event::EventHandler for PlayState {
         _keymod: event::KeyMods,
         repeat: bool,
     ) {
-        match keycode {
-            event::KeyCode::Space if self.mode == PlayMode::Pending => {
-                self.mode = PlayMode::Running;
-            }
-            event::KeyCode::Left if self.mode != PlayMode::Lost => {
-                self.speed.1 = 1.0;
-                if self.paddle > LIMIT_LEFT {
-                    // repeat press increases speed
-                    if repeat {
-                        self.speed.0 *= 1.2;
-                    } else {
-                        self.speed.0 = 1.0;
-                    }
-                    let mut delta = PADDLE_SPEED * self.speed.0;
-                    self.paddle -= delta;
-                    // stay in bounds
-                    if self.paddle < LIMIT_LEFT {
-                        delta -= LIMIT_LEFT - self.paddle;
-                        self.paddle = LIMIT_LEFT;
-                    }
-                    if self.mode == PlayMode::Pending {
-                        self.ball.0 -= delta;
-                    }
-                }
-            }
-            event::KeyCode::Right if self.mode != PlayMode::Lost => {
-                self.speed.0 = 1.0;
-                if self.paddle < LIMIT_RIGHT {
-                     // repeat press increases speed
-                    if repeat {
-                        self.speed.1 *= 1.2;
-                    } else {
-                        self.speed.1 = 1.0;
-                    }
-
-                    let mut delta = PADDLE_SPEED * self.speed.1;
-                    self.paddle += delta;
-                    // stay in bounds
-                    if self.paddle > LIMIT_RIGHT {
-                        delta -= self.paddle - LIMIT_RIGHT;
-                        self.paddle = LIMIT_RIGHT;
-                    }
-                    if self.mode == PlayMode::Pending {
-                        self.ball.0 += delta;
-                    }
-                }
-            }
-            _ => (),
-        }
+        self.controller.key_down(keycode, repeat);
+    }
+
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        self.controller.mouse_motion(x, y);
     }
 }
 
 impl InnerState for PlayState {
-    fn transition(&self, font: graphics::Font, keycode: event::KeyCode) -> Transition {
-        if self.mode == PlayMode::Running && keycode == event::KeyCode::Space {
-            return Transition::Push(Box::new(PauseState::new(font)));
+    fn transition(&self, keycode: event::KeyCode) -> Transition {
+        // any key ends a demo
+        if self.demo {
+            return self.title();
+        }
+        // P always pauses, space only when the game has no use for it
+        let space_pauses = keycode == event::KeyCode::Space && !self.sim.launch_in_play();
+        if self.sim.mode == PlayMode::Running && (keycode == event::KeyCode::P || space_pauses) {
+            return Transition::Push(Box::new(PauseState::new(self.assets.font)));
         }
         Transition::None
     }
 
-    fn state_transition(&self, font: graphics::Font) -> Transition {
-        if self.mode == PlayMode::Lost {
-            return Transition::Replace(Box::new(EndState::new("GAME OVER",font)));
-        } else if self.mode == PlayMode::Won {
-            return Transition::Replace(Box::new(PlayState::new(font,self.level.index+1,self.score)));
+    fn take_sounds(&mut self) -> Vec<Sfx> {
+        std::mem::take(&mut self.sounds)
+    }
+
+    fn music(&self) -> Option<Music> {
+        Some(Music::Level(self.sim.level.index))
+    }
+
+    fn commands(&mut self) -> Option<&mut dyn Commands> {
+        if self.demo {
+            return None;
+        }
+        Some(self)
+    }
+
+    fn draw_debug(&self, ctx: &mut Context) -> GameResult {
+        debug::draw_sim(ctx, &self.sim)
+    }
+
+    // restart the level with its new layout or colors
+    fn reload(&self) -> Option<Box<dyn InnerState>> {
+        if self.demo {
+            return None;
+        }
+        let mut play = PlayState::new(
+            self.assets.clone(),
+            self.options.clone(),
+            self.sim.level.index,
+            self.sim.score,
+            self.sim.lives,
+        );
+        play.attack = self.attack.clone();
+        Some(Box::new(play))
+    }
+
+    fn state_transition(&self) -> Transition {
+        let timed_out = matches!(self.max_ticks, Some(max) if self.sim.ticks >= max);
+        if self.demo && (self.sim.finished() || timed_out) {
+            return self.title();
+        }
+        if self.sim.mode == PlayMode::Lost {
+            return Transition::Replace(Box::new(EndState::new(
+                "GAME OVER",
+                self.assets.clone(),
+                self.options.clone(),
+            )));
+        } else if self.sim.mode == PlayMode::Won {
+            let mut attack = self.attack.clone();
+            if let Some(attack) = &mut attack {
+                attack.finish_level(self.sim.level.index, self.sim.ticks);
+            }
+            return Transition::Animate(
+                Box::new(LevelCompleteState::new(
+                    self.assets.clone(),
+                    self.options.clone(),
+                    self.sim.stats.clone(),
+                    self.sim.score,
+                    self.sim.lives,
+                    attack,
+                )),
+                Effect::Fade,
+            );
         }
         Transition::None
     }
 }
 
+// Cheats, for testing
+impl Commands for PlayState {
+    fn run(&mut self, command: &Command) -> Result<String, String> {
+        let (recording, attack) = (self.recording.take(), self.attack.take());
+        let result = self.cheat(command);
+        // a failed cheat changed nothing
+        if result.is_err() {
+            self.recording = recording;
+            self.attack = attack;
+            return result;
+        }
+        // a replay cannot be played back once cheats changed the game
+        if recording.is_some() {
+            println!("Cheat used, replay not recorded");
+        }
+        // nor can a time attack run count
+        if attack.is_some() {
+            println!("Cheat used, time attack stopped");
+        }
+        result
+    }
+}
 
-pub const PADDLE_WIDTH: f32 = 80.0;
-pub const PADDLE_HEIGHT: f32 = 20.0;
+impl PlayState {
+    // apply a cheat
+    fn cheat(&mut self, command: &Command) -> Result<String, String> {
+        match command {
+            Command::Level(index) => {
+                let god = self.sim.god;
+                *self = PlayState::new(
+                    self.assets.clone(),
+                    self.options.clone(),
+                    *index,
+                    self.sim.score,
+                    self.sim.lives,
+                );
+                self.recording = None;
+                self.sim.god = god;
+                Ok(format!("level {}: {}", index, self.sim.level.name))
+            }
+            Command::Clear => {
+                self.sim.clear();
+                Ok("all blocks broken".to_owned())
+            }
+            Command::Speed(speed) if *speed > 0.0 => {
+                self.sim.set_speed(*speed);
+                Ok(format!("ball speed {}", speed))
+            }
+            Command::Speed(_) => Err("speed must be positive".to_owned()),
+            Command::Spawn(kind) => {
+                let powerup: PowerUp = kind.parse()?;
+                self.sim.power_up(powerup);
+                Ok(format!("{:?} power-up", powerup))
+            }
+            Command::Lives(lives) if *lives > 0 => {
+                self.sim.lives = *lives;
+                Ok(format!("{} lives", lives))
+            }
+            Command::Lives(_) => Err("at least one life is needed".to_owned()),
+            Command::God => {
+                self.sim.god = !self.sim.god;
+                Ok(format!("god mode {}", if self.sim.god { "on" } else { "off" }))
+            }
+            Command::Seed(seed) => {
+                self.sim.reseed(*seed);
+                Ok(format!("seed {}", seed))
+            }
+        }
+    }
+}
 
 pub const PADDLE_INNERWIDTH: f32 = 60.0;
 
-pub const LIMIT_LEFT: f32 = PADDLE_WIDTH / 2.0 + 10.0;
-pub const LIMIT_RIGHT: f32 = WIDTH - PADDLE_WIDTH / 2.0 - 10.0;
+// How long a demo plays before going back to the title screen
+const DEMO_SECS: u32 = 30;
+
+// Dots showing the launch direction without the preview assist
+const AIM_DOTS: usize = 3;
+
+// Width of the boss health bar when full
+const HEALTH_BAR_WIDTH: f32 = 300.0;
 
-const PADDLE_SPEED: f32 = 8.0;
-const BALL_RADIUS: f32 = 10.0;
+// Width of the open warp exit drawn in the wall
+const WARP_GATE_WIDTH: f32 = 6.0;
 
+// Opacity of speed zones, so blocks show through
+const SPEED_ZONE_ALPHA: f32 = 0.3;
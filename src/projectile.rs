@@ -0,0 +1,85 @@
+//! Projectiles: laser shots fired by the paddle, and power-up capsules falling towards it
+
+use ggez::graphics;
+use std::str::FromStr;
+
+// A power-up, caught by the paddle
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PowerUp {
+    Laser, // the paddle fires shots upwards
+    Catch, // the paddle holds the ball until it is launched again
+}
+
+impl FromStr for PowerUp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "laser" => Ok(PowerUp::Laser),
+            "catch" => Ok(PowerUp::Catch),
+            _ => Err(format!("unknown power-up '{}', expected laser or catch", s)),
+        }
+    }
+}
+
+// What a projectile is
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Kind {
+    Laser,            // shot going up, breaking the first block it meets
+    Capsule(PowerUp), // capsule falling down, giving a power-up to the paddle
+}
+
+// Something flying up or down the playfield
+#[derive(Clone, Copy, Debug)]
+pub struct Projectile {
+    pub kind: Kind,           // what it is
+    pub rect: graphics::Rect, // position
+    speed: f32,               // vertical speed
+}
+
+impl Projectile {
+    // laser shot fired from the given point of the paddle
+    pub fn laser(x: f32, y: f32) -> Self {
+        Self {
+            kind: Kind::Laser,
+            rect: graphics::Rect::new(
+                x - LASER_WIDTH / 2.0,
+                y - LASER_HEIGHT,
+                LASER_WIDTH,
+                LASER_HEIGHT,
+            ),
+            speed: -LASER_SPEED,
+        }
+    }
+
+    // capsule dropped from the given point, usually the center of a broken block
+    pub fn capsule(powerup: PowerUp, x: f32, y: f32) -> Self {
+        Self {
+            kind: Kind::Capsule(powerup),
+            rect: graphics::Rect::new(
+                x - CAPSULE_WIDTH / 2.0,
+                y - CAPSULE_HEIGHT / 2.0,
+                CAPSULE_WIDTH,
+                CAPSULE_HEIGHT,
+            ),
+            speed: CAPSULE_SPEED,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.rect.y += self.speed;
+    }
+
+    // has it left the playfield?
+    pub fn gone(&self) -> bool {
+        self.rect.y + self.rect.h < 0.0 || self.rect.y > 420.0
+    }
+}
+
+const LASER_WIDTH: f32 = 4.0;
+const LASER_HEIGHT: f32 = 12.0;
+const LASER_SPEED: f32 = 10.0;
+
+const CAPSULE_WIDTH: f32 = 30.0;
+const CAPSULE_HEIGHT: f32 = 12.0;
+const CAPSULE_SPEED: f32 = 2.0;
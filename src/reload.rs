@@ -0,0 +1,109 @@
+//! Development mode: reload levels and themes when their files change
+
+use ggez::graphics;
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::*;
+use crate::assets::{file_name, Assets, LEVELS_DIR, THEMES_DIR};
+use crate::level::LevelDef;
+use crate::theme::Theme;
+
+// Polls directories for new or modified files
+pub struct Watcher {
+    levels_dir: PathBuf,                  // directory of level files
+    themes_dir: PathBuf,                  // directory of theme files
+    stamps: HashMap<PathBuf, SystemTime>, // last modification time of known files
+    last_check: Instant,
+}
+
+impl Watcher {
+    // watch the levels and themes directories under the resource directory,
+    // or the given levels directory
+    pub fn new(resource_dir: &Path, levels_dir: Option<&Path>) -> Self {
+        let mut watcher = Self {
+            levels_dir: levels_dir
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| resource_dir.join(LEVELS_DIR.trim_start_matches('/'))),
+            themes_dir: resource_dir.join(THEMES_DIR.trim_start_matches('/')),
+            stamps: HashMap::new(),
+            last_check: Instant::now(),
+        };
+        // files already there have been loaded at startup
+        watcher.changed();
+        watcher
+    }
+
+    // files created or modified since the last check, checking at most every POLL_MILLIS
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if Instant::now() - self.last_check < Duration::from_millis(POLL_MILLIS) {
+            return Vec::new();
+        }
+        self.last_check = Instant::now();
+        self.changed()
+    }
+
+    fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for dir in &[&self.levels_dir, &self.themes_dir] {
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension() != Some(OsStr::new("txt")) {
+                    continue;
+                }
+                if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                    if self.stamps.insert(path.clone(), modified) != Some(modified) {
+                        changed.push(path);
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    // read a changed file into the assets, the error is meant to be shown on screen
+    pub fn reload(&self, assets: &Assets, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let error = |e| format!("{}: {}", path.display(), e);
+        if path.starts_with(&self.levels_dir) {
+            let def = LevelDef::parse(&text).map_err(error)?;
+            assets.set_level(&file_name(path), def);
+        } else {
+            let theme = Theme::parse(&text).map_err(error)?;
+            assets.set_theme(&file_name(path), theme);
+        }
+        println!("Reloaded {}", path.display());
+        Ok(())
+    }
+}
+
+// draw a reload error over the current screen
+pub fn draw_error(ctx: &mut Context, font: graphics::Font, message: &str) -> GameResult {
+    let mut text = graphics::Text::new((message, font, 16.0));
+    text.set_bounds(
+        na::Point2::new(WIDTH - 20.0, f32::INFINITY),
+        graphics::Align::Left,
+    );
+    let (_w, h) = text.dimensions(ctx);
+    let mb = &mut graphics::MeshBuilder::new();
+    mb.rectangle(
+        graphics::DrawMode::Fill(graphics::FillOptions::default()),
+        graphics::Rect::new(0.0, 0.0, WIDTH, h as f32 + 20.0),
+        RED,
+    );
+    let m = &mb.build(ctx)?;
+    graphics::draw(ctx, m, graphics::DrawParam::new())?;
+    graphics::draw(ctx, &text, (na::Point2::new(10.0, 10.0), graphics::WHITE))
+}
+
+// How often files are checked for changes
+const POLL_MILLIS: u64 = 500;
@@ -0,0 +1,124 @@
+//! Recording and playing back the inputs of a level
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::level::{parse_value, ParseError};
+use crate::sim::{Direction, Input};
+
+// Inputs given while playing a level, with the tick they happened at
+// The seed and the starting score and lives are enough to play the level again exactly
+#[derive(Clone, Debug)]
+pub struct Replay {
+    pub index: u32,                // level number
+    pub score: u32,                // score when the level started
+    pub lives: u32,                // lives when the level started
+    pub seed: u64,                 // seed of the level random numbers
+    pub inputs: Vec<(u32, Input)>, // inputs, in tick order
+    next: usize,                   // next input to play back
+}
+
+impl Replay {
+    pub fn new(index: u32, score: u32, lives: u32, seed: u64) -> Self {
+        Self {
+            index,
+            score,
+            lives,
+            seed,
+            inputs: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn record(&mut self, tick: u32, input: Input) {
+        self.inputs.push((tick, input));
+    }
+
+    // inputs to play back before the given tick
+    pub fn take(&mut self, tick: u32) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        while let Some((t, input)) = self.inputs.get(self.next) {
+            if *t > tick {
+                break;
+            }
+            inputs.push(*input);
+            self.next += 1;
+        }
+        inputs
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Replay::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_string()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Parse a replay file
+    // "key value" settings, then one input per line: the tick, "left", "right" or "launch",
+    // and "held" if the key was held down, or "aim" and the launch angle
+    fn parse(text: &str) -> Result<Replay, ParseError> {
+        let mut replay = Replay::new(0, 0, 0, 0);
+        for (ix, line) in text.lines().enumerate() {
+            let line_no = ix + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let key = words.next().unwrap_or_default();
+            let args: Vec<&str> = words.collect();
+            match key {
+                "level" => replay.index = parse_value(line_no, &args)?,
+                "score" => replay.score = parse_value(line_no, &args)?,
+                "lives" => replay.lives = parse_value(line_no, &args)?,
+                "seed" => replay.seed = parse_value(line_no, &args)?,
+                _ => {
+                    let tick = parse_value(line_no, &[key])?;
+                    let held = args.get(1) == Some(&"held");
+                    let input = match args.first() {
+                        Some(&"aim") => Input::Aim(parse_value(line_no, &args[1..])?),
+                        Some(&"left") => Input::Move(Direction::Left, held),
+                        Some(&"right") => Input::Move(Direction::Right, held),
+                        Some(&"launch") => Input::Launch,
+                        _ => return Err(ParseError::new(line_no, "expected an input")),
+                    };
+                    replay.record(tick, input);
+                }
+            }
+        }
+        if replay.lives == 0 {
+            return Err(ParseError::new(text.lines().count(), "missing lives"));
+        }
+        Ok(replay)
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "level {}", self.index)?;
+        writeln!(f, "score {}", self.score)?;
+        writeln!(f, "lives {}", self.lives)?;
+        writeln!(f, "seed {}", self.seed)?;
+        for (tick, input) in self.inputs.iter() {
+            let (name, held) = match input {
+                Input::Move(Direction::Left, held) => ("left", *held),
+                Input::Move(Direction::Right, held) => ("right", *held),
+                Input::Launch => ("launch", false),
+                Input::Aim(angle) => {
+                    writeln!(f, "{} aim {}", tick, angle)?;
+                    continue;
+                }
+            };
+            if held {
+                writeln!(f, "{} {} held", tick, name)?;
+            } else {
+                writeln!(f, "{} {}", tick, name)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+//! Scoring rules and score popups
+
+use ggez::graphics;
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+use std::time::Duration;
+
+use super::*;
+use crate::level::*;
+
+// Base points for breaking a block of the given kind
+pub fn block_value(kind: BlockKind) -> u32 {
+    match kind {
+        BlockKind::Light => 1,
+        BlockKind::Dark => 2,
+        BlockKind::Hidden => 5,
+    }
+}
+
+// Multiplier for the nth block hit without touching the paddle (starting at 1)
+pub fn combo_multiplier(combo: u32) -> u32 {
+    combo.clamp(1, MAX_COMBO_MULTIPLIER)
+}
+
+// Faster levels are worth more
+pub fn speed_multiplier(level: &Level) -> f32 {
+    level.ball_speed / BALL_SPEED
+}
+
+// Points for breaking a block as part of the given combo
+pub fn block_points(block: &Block, combo: u32, level: &Level) -> u32 {
+    let points = block_value(block.kind) * combo_multiplier(combo);
+    (points as f32 * speed_multiplier(level)).round() as u32
+}
+
+// Points for destroying an enemy
+pub fn enemy_points(level: &Level) -> u32 {
+    (ENEMY_POINTS as f32 * speed_multiplier(level)).round() as u32
+}
+
+// Points for hitting the boss, with a bonus for the last hit
+pub fn boss_points(damage: u32, defeated: bool, level: &Level) -> u32 {
+    let mut points = damage * BOSS_HIT_POINTS;
+    if defeated {
+        points += BOSS_BONUS;
+    }
+    (points as f32 * speed_multiplier(level)).round() as u32
+}
+
+// What happened during a level
+#[derive(Clone, Debug)]
+pub struct LevelStats {
+    pub index: u32,      // level number
+    pub blocks: u32,     // blocks broken
+    pub time: Duration,  // time spent with the ball in play
+    pub max_combo: u32,  // most blocks hit without touching the paddle
+    pub lives_lost: u32, // balls lost
+    pub skipped: u32,    // levels skipped through a warp exit
+}
+
+impl LevelStats {
+    pub fn new(index: u32) -> Self {
+        Self {
+            index,
+            blocks: 0,
+            time: Duration::from_secs(0),
+            max_combo: 0,
+            lives_lost: 0,
+            skipped: 0,
+        }
+    }
+
+    // points for each second under par
+    pub fn time_bonus(&self) -> u32 {
+        TIME_PAR_SECS.saturating_sub(self.time.as_secs() as u32) * TIME_BONUS_PER_SEC
+    }
+
+    // points for each level skipped through a warp exit
+    pub fn warp_bonus(&self) -> u32 {
+        self.skipped * WARP_BONUS_PER_LEVEL
+    }
+
+    pub fn no_life_lost_bonus(&self) -> u32 {
+        if self.lives_lost == 0 {
+            NO_LIFE_LOST_BONUS
+        } else {
+            0
+        }
+    }
+}
+
+// Floating "+N" text, shown where points were scored
+pub struct Popup {
+    text: graphics::Text,
+    pos: (f32, f32), // current position (center)
+    age: u32,        // updates since creation
+}
+
+impl Popup {
+    // popup centered on the given position
+    pub fn new(font: graphics::Font, points: u32, center: (f32, f32)) -> Self {
+        let text = graphics::Text::new((format!("+{}", points), font, 16.0));
+        Self {
+            text,
+            pos: center,
+            age: 0,
+        }
+    }
+
+    // move up a bit
+    pub fn update(&mut self) {
+        self.age += 1;
+        self.pos.1 -= POPUP_RISE;
+    }
+
+    pub fn expired(&self) -> bool {
+        self.age >= POPUP_UPDATES
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        let (w, h) = self.text.dimensions(ctx);
+        let dest_point = na::Point2::new(
+            self.pos.0 - w as f32 / 2.0,
+            self.pos.1 - h as f32 / 2.0,
+        );
+        // fade out as the popup gets older
+        let mut color = RED;
+        color.a = 1.0 - self.age as f32 / POPUP_UPDATES as f32;
+        graphics::draw(ctx, &self.text, (dest_point, color))
+    }
+}
+
+// Levels finished faster than this get a time bonus
+const TIME_PAR_SECS: u32 = 90;
+const TIME_BONUS_PER_SEC: u32 = 1;
+const NO_LIFE_LOST_BONUS: u32 = 25;
+// Leaving through a warp exit is worth the levels skipped
+const WARP_BONUS_PER_LEVEL: u32 = 50;
+
+// Highest combo multiplier
+const MAX_COMBO_MULTIPLIER: u32 = 5;
+
+// Base points for destroying an enemy
+const ENEMY_POINTS: u32 = 5;
+
+// Base points for each hit point taken from the boss, and for defeating it
+const BOSS_HIT_POINTS: u32 = 2;
+const BOSS_BONUS: u32 = 100;
+
+// How long a popup stays visible, in updates
+const POPUP_UPDATES: u32 = 45;
+// How much a popup rises each update
+const POPUP_RISE: f32 = 0.5;
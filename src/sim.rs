@@ -0,0 +1,990 @@
+//! Game simulation, independent of the window, input and rendering
+
+use ggez::graphics;
+use ggez::nalgebra as na;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use super::*;
+use crate::boss::*;
+use crate::enemy::*;
+use crate::fixture::Touch;
+use crate::level::*;
+use crate::projectile::*;
+use crate::scoring::{block_points, boss_points, enemy_points};
+use crate::scoring::LevelStats;
+
+// Different modes a level can be in
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum PlayMode {
+    Pending,
+    Running,
+    Lost,
+    Won,
+}
+
+// Bounce direction on a block
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Bounce {
+    Bottom,
+    Top,
+    Left,
+    Right,
+    Slope, // off a side that is not horizontal or vertical
+    None,
+}
+
+// Wall of the playfield the ball bounces on
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Wall {
+    Left,
+    Right,
+    Top,
+}
+
+// Paddle movement
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+// Player input
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Input {
+    Move(Direction, bool), // move the paddle, true if the key is held down
+    Launch,                // launch the ball
+    Aim(f32),              // launch angle from the vertical, in radians
+}
+
+// Something that happened during a tick, to play sounds and show effects
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+    Block { block: Block, combo: u32, points: u32 }, // block broken
+    Paddle,                                          // ball bounced on the paddle
+    Enemy { pos: (f32, f32), points: u32 },          // enemy destroyed
+    Boss { pos: (f32, f32), points: u32 },           // boss hit, at the given position
+    PowerUp(PowerUp),                                // power-up caught by the paddle
+    Fire,                                            // laser shots fired
+    WarpOpen,                                        // the warp exit opened
+    Fixture,                                         // ball touched a bumper, one-way wall or portal
+}
+
+// Details of the collision tests, for the debug overlay
+#[derive(Clone, Debug)]
+pub struct Trace {
+    pub ball_rect: graphics::Rect,   // ball approximation in the last test
+    pub tested: Vec<graphics::Rect>, // blocks close enough to the ball to be tested
+    pub hit: Vec<graphics::Rect>,    // blocks hit in the last bounce on blocks
+    pub bounce: Bounce,              // side of the last bounce on blocks
+}
+
+// A level being played
+pub struct Sim {
+    pub mode: PlayMode,               // current mode
+    pub level: Level,                 // level definition
+    pub paddle: f32,                  // paddle x position
+    pub ball: (f32, f32),             // ball position
+    speed: (f32, f32),                // paddle speed (left/right)
+    last_paddle: f32,                 // paddle x position at the previous tick
+    pub ball_speed: (f32, f32),       // ball speed vector
+    pub score: u32,                   // user score
+    pub lives: u32,                   // balls left, including the one in play
+    pub combo: u32,                   // blocks hit since the ball last touched the paddle
+    pub stats: LevelStats,            // statistics for the level summary
+    pub ticks: u32,                   // ticks since the level started
+    pub trace: Trace,                 // last collision tests
+    pub god: bool,                    // cheat: the ball bounces at the bottom instead of falling
+    pub idle_bounces: u32,            // wall bounces since the ball last touched a block or the paddle
+    base_speed: f32,                  // ball speed before any speed up
+    paddle_hits: u32,                 // paddle hits since the last speed up
+    top_reached: bool,                // has the ball hit the top wall since the last ball was lost?
+    row_reached: bool,                // has the ball broken a block in the speed up row?
+    pub enemies: Vec<Enemy>,          // enemies on screen
+    spawn_ticks: u32,                 // ticks before the next enemy comes in
+    pub boss: Option<Boss>,           // boss of a boss level
+    pub projectiles: Vec<Projectile>, // laser shots and capsules in flight
+    pub laser_ticks: u32,             // ticks left with the laser power-up
+    pub catch_ticks: u32,             // ticks left with the catch power-up
+    pub caught: Option<(f32, u32)>,   // held ball: offset from the paddle center, ticks before release
+    pub aim: f32,                     // launch angle from the vertical, in radians
+    pub warp_open: bool,              // can the paddle leave through the warp exit?
+    powered_up: bool,                 // has the paddle caught a power-up in this level?
+    fire_ticks: u32,                  // ticks before the laser can fire again
+    events: Vec<Event>,               // events since last call to take_events
+    rng: StdRng,                      // random numbers, seeded so games can be replayed
+}
+
+impl Sim {
+    pub fn new(level: Level, score: u32, lives: u32, seed: u64) -> Self {
+        let speed = level.ball_speed;
+        let index = level.index;
+        let boss = level.boss.map(Boss::new);
+        let mut rng = StdRng::seed_from_u64(seed);
+        // a slightly random angle, so no two games are the same
+        let aim = rng.gen_range(-LAUNCH_JITTER, LAUNCH_JITTER);
+        Self {
+            mode: PlayMode::Pending,
+            level,
+            paddle: WIDTH / 2.0,
+            ball: (WIDTH / 2.0, 390.0),
+            speed: (1.0, 1.0),
+            last_paddle: WIDTH / 2.0,
+            ball_speed: (0.0, -speed),
+            score,
+            lives,
+            combo: 0,
+            stats: LevelStats::new(index),
+            ticks: 0,
+            trace: Trace {
+                ball_rect: graphics::Rect::default(),
+                tested: Vec::new(),
+                hit: Vec::new(),
+                bounce: Bounce::None,
+            },
+            god: false,
+            idle_bounces: 0,
+            base_speed: speed,
+            paddle_hits: 0,
+            top_reached: false,
+            row_reached: false,
+            enemies: Vec::new(),
+            spawn_ticks: 0,
+            boss,
+            projectiles: Vec::new(),
+            laser_ticks: 0,
+            catch_ticks: 0,
+            caught: None,
+            aim,
+            warp_open: false,
+            powered_up: false,
+            fire_ticks: 0,
+            events: Vec::new(),
+            rng,
+        }
+    }
+
+    // does launching do something with the ball in play: release a caught ball or fire the laser?
+    pub fn launch_in_play(&self) -> bool {
+        self.mode == PlayMode::Running && (self.caught.is_some() || self.laser_ticks > 0)
+    }
+
+    // is there a boss still alive?
+    pub fn boss_alive(&self) -> bool {
+        matches!(&self.boss, Some(boss) if !boss.defeated())
+    }
+
+    // is the level over?
+    pub fn finished(&self) -> bool {
+        self.mode == PlayMode::Lost || self.mode == PlayMode::Won
+    }
+
+    // events since last call
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    // advance the game by one tick
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+        if self.level.moving {
+            self.move_blocks();
+        }
+        // update ball and calculate collisions
+        if self.mode == PlayMode::Running {
+            self.stats.time += Duration::from_secs(1) / TICKS_PER_SECOND;
+            if self.caught.is_some() {
+                self.hold_ball();
+            } else {
+                // speed zones change how far the ball goes, not its speed
+                let factor: f32 = self.level.fixtures.iter().map(|f| f.speed_factor(self.ball)).product();
+                self.ball.0 += self.ball_speed.0 * factor;
+                self.ball.1 += self.ball_speed.1 * factor;
+                self.collision();
+            }
+            if self.mode == PlayMode::Running {
+                self.update_enemies();
+            }
+            if self.mode == PlayMode::Running {
+                self.update_boss();
+            }
+            if self.mode == PlayMode::Running {
+                self.update_projectiles();
+            }
+            if !self.warp_open && self.warp_condition() {
+                self.warp_open = true;
+                self.events.push(Event::WarpOpen);
+            }
+        }
+        self.last_paddle = self.paddle;
+    }
+
+    // ball held on the paddle: follow it until it is released
+    fn hold_ball(&mut self) {
+        if let Some((offset, ticks)) = self.caught {
+            self.ball = (self.paddle + offset, 400.0 - BALL_RADIUS);
+            if ticks == 0 {
+                self.release();
+            } else {
+                self.caught = Some((offset, ticks - 1));
+            }
+        }
+    }
+
+    // let go of a held ball, at the angle given by where it sits on the paddle
+    fn release(&mut self) {
+        self.caught = None;
+        self.paddle_bounce();
+    }
+
+    // put moving blocks where they are at the current tick
+    fn move_blocks(&mut self) {
+        let time = self.ticks as f32 / TICKS_PER_SECOND as f32;
+        self.level.blocks = self
+            .level
+            .blocks
+            .drain()
+            .map(|mut b| {
+                b.rect = b.position(time);
+                b
+            })
+            .collect();
+    }
+
+    // apply player input
+    pub fn input(&mut self, input: Input) {
+        match input {
+            Input::Launch if self.caught.is_some() => self.release(),
+            // with the laser, launching fires once the ball is in play
+            Input::Launch
+                if self.mode == PlayMode::Running && self.laser_ticks > 0 && self.fire_ticks == 0 =>
+            {
+                let half = PADDLE_WIDTH / 2.0;
+                self.projectiles.push(Projectile::laser(self.paddle - half, 400.0));
+                self.projectiles.push(Projectile::laser(self.paddle + half, 400.0));
+                self.fire_ticks = FIRE_TICKS;
+                self.events.push(Event::Fire);
+            }
+            Input::Launch if self.mode == PlayMode::Pending => {
+                self.mode = PlayMode::Running;
+                let speed = self.level.ball_speed;
+                self.ball_speed = (speed * self.aim.sin(), -speed * self.aim.cos());
+            }
+            Input::Aim(angle) if self.mode == PlayMode::Pending => {
+                self.aim = angle.clamp(-MAX_AIM, MAX_AIM);
+            }
+            Input::Move(side, _) if self.at_open_warp(side) => self.warp(),
+            Input::Move(Direction::Left, repeat) if self.mode != PlayMode::Lost => {
+                self.speed.1 = 1.0;
+                if self.paddle > LIMIT_LEFT {
+                    // repeat press increases speed
+                    if repeat {
+                        self.speed.0 *= 1.2;
+                    } else {
+                        self.speed.0 = 1.0;
+                    }
+                    let mut delta = PADDLE_SPEED * self.speed.0;
+                    self.paddle -= delta;
+                    // stay in bounds
+                    if self.paddle < LIMIT_LEFT {
+                        delta -= LIMIT_LEFT - self.paddle;
+                        self.paddle = LIMIT_LEFT;
+                    }
+                    if self.mode == PlayMode::Pending {
+                        self.ball.0 -= delta;
+                    }
+                }
+            }
+            Input::Move(Direction::Right, repeat) if self.mode != PlayMode::Lost => {
+                self.speed.0 = 1.0;
+                if self.paddle < LIMIT_RIGHT {
+                    // repeat press increases speed
+                    if repeat {
+                        self.speed.1 *= 1.2;
+                    } else {
+                        self.speed.1 = 1.0;
+                    }
+
+                    let mut delta = PADDLE_SPEED * self.speed.1;
+                    self.paddle += delta;
+                    // stay in bounds
+                    if self.paddle > LIMIT_RIGHT {
+                        delta -= self.paddle - LIMIT_RIGHT;
+                        self.paddle = LIMIT_RIGHT;
+                    }
+                    if self.mode == PlayMode::Pending {
+                        self.ball.0 += delta;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // has the level's warp condition been met?
+    fn warp_condition(&self) -> bool {
+        match self.level.warp.map(|w| w.condition) {
+            Some(WarpCondition::PowerUp) => self.powered_up,
+            Some(WarpCondition::Time(secs)) => self.stats.time.as_secs_f32() >= secs,
+            Some(WarpCondition::Block(i, j)) => !self.level.blocks.iter().any(|b| b.i == i && b.j == j),
+            None => false,
+        }
+    }
+
+    // is the paddle pushing against the wall with the open warp exit?
+    fn at_open_warp(&self, side: Direction) -> bool {
+        let limit = match side {
+            Direction::Left => LIMIT_LEFT,
+            Direction::Right => LIMIT_RIGHT,
+        };
+        self.warp_open
+            && self.mode == PlayMode::Running
+            && matches!(self.level.warp, Some(w) if w.side == side)
+            && self.paddle == limit
+    }
+
+    // leave the level through the warp exit
+    fn warp(&mut self) {
+        if let Some(warp) = self.level.warp {
+            self.stats.skipped = warp.skip;
+            self.mode = PlayMode::Won;
+        }
+    }
+
+    // where the ball will go when launched, as points along the way: up to the top wall,
+    // the first block, or the second bounce on a side wall
+    pub fn launch_path(&self) -> Vec<(f32, f32)> {
+        let (mut x, mut y) = self.ball;
+        let (mut dx, dy) = (self.aim.sin() * PATH_STEP, -self.aim.cos() * PATH_STEP);
+        let mut path = Vec::new();
+        let mut bounces = 0;
+        while y - BALL_RADIUS > 0.0 && path.len() < PATH_POINTS {
+            x += dx;
+            y += dy;
+            if x - BALL_RADIUS <= 0.0 || x + BALL_RADIUS >= WIDTH {
+                bounces += 1;
+                if bounces > 1 {
+                    break;
+                }
+                dx = -dx;
+            }
+            let point = na::Point2::new(x, y - BALL_RADIUS);
+            // hidden blocks stay secret
+            if self.level.blocks.iter().any(|b| b.kind != BlockKind::Hidden && b.rect.contains(point)) {
+                break;
+            }
+            path.push((x, y));
+        }
+        path
+    }
+
+    // cheat: break all blocks at once
+    pub fn clear(&mut self) {
+        self.stats.blocks += self.level.blocks.len() as u32;
+        self.level.blocks.clear();
+        self.enemies.clear();
+        if let Some(boss) = &mut self.boss {
+            boss.health = 0;
+        }
+        self.mode = PlayMode::Won;
+    }
+
+    // give a power-up to the paddle
+    pub fn power_up(&mut self, powerup: PowerUp) {
+        match powerup {
+            PowerUp::Laser => self.laser_ticks = LASER_SECS * TICKS_PER_SECOND,
+            PowerUp::Catch => self.catch_ticks = CATCH_SECS * TICKS_PER_SECOND,
+        }
+        self.powered_up = true;
+        self.events.push(Event::PowerUp(powerup));
+    }
+
+    // cheat: change the ball speed, keeping its direction
+    pub fn set_speed(&mut self, speed: f32) {
+        self.base_speed = speed;
+        self.change_speed(speed);
+    }
+
+    // change the ball speed, keeping its direction
+    fn change_speed(&mut self, speed: f32) {
+        let (sx, sy) = self.ball_speed;
+        let current = (sx * sx + sy * sy).sqrt();
+        self.ball_speed = (sx * speed / current, sy * speed / current);
+        self.level.ball_speed = speed;
+    }
+
+    // make the ball faster, up to the level maximum
+    fn speed_up(&mut self) {
+        let speedup = self.level.speedup;
+        let speed = (self.level.ball_speed + speedup.step).min(speedup.max_speed);
+        if speed > self.level.ball_speed {
+            self.change_speed(speed);
+        }
+    }
+
+    // cheat: restart the random numbers
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    // area where the ball center bounces on the paddle
+    pub fn paddle_zone(&self) -> graphics::Rect {
+        let half = PADDLE_WIDTH / 2.0 + PADDLE_END + BALL_RADIUS;
+        graphics::Rect::new(
+            self.paddle - half,
+            400.0 - BALL_RADIUS,
+            half * 2.0,
+            20.0,
+        )
+    }
+
+    // ball fell below the paddle: put a new one on the paddle, or end the game
+    fn lose_ball(&mut self) {
+        self.lives -= 1;
+        self.stats.lives_lost += 1;
+        self.combo = 0;
+        self.idle_bounces = 0;
+        // back to the initial speed
+        self.level.ball_speed = self.base_speed;
+        self.paddle_hits = 0;
+        self.top_reached = false;
+        self.row_reached = false;
+        self.enemies.clear();
+        self.spawn_ticks = 0;
+        if let Some(boss) = &mut self.boss {
+            boss.shots.clear();
+        }
+        self.projectiles.clear();
+        self.laser_ticks = 0;
+        self.catch_ticks = 0;
+        self.caught = None;
+        if self.lives == 0 {
+            self.mode = PlayMode::Lost;
+        } else {
+            self.mode = PlayMode::Pending;
+            self.aim = self.rng.gen_range(-LAUNCH_JITTER, LAUNCH_JITTER);
+            self.ball = (self.paddle, 390.0);
+            self.ball_speed = (0.0, -self.level.ball_speed);
+        }
+    }
+
+    // after a bounce on a wall: keep the ball from looping forever without reaching anything
+    fn wall_bounce(&mut self, wall: Wall) {
+        self.idle_bounces += 1;
+        let (mut sx, mut sy) = self.ball_speed;
+        let speed = (sx * sx + sy * sy).sqrt();
+        // same path for too long: change the angle a bit at each bounce until it reaches something
+        if self.idle_bounces >= STUCK_BOUNCES {
+            let nudge = self.rng.gen_range(-STUCK_NUDGE, STUCK_NUDGE);
+            let (sin, cos) = nudge.sin_cos();
+            let (nx, ny) = (sx * cos - sy * sin, sx * sin + sy * cos);
+            sx = nx;
+            sy = ny;
+        }
+        // almost horizontal: the ball would go from wall to wall for ages
+        let min_vertical = speed * MIN_ANGLE.sin();
+        if sy.abs() < min_vertical {
+            // go down when there is no vertical speed at all, so the player gets the ball back
+            sy = min_vertical.copysign(sy);
+            sx = sx.signum() * (speed * speed - sy * sy).sqrt();
+        }
+        // whatever the changes above, leave the wall instead of going back into it
+        match wall {
+            Wall::Left => sx = sx.abs(),
+            Wall::Right => sx = -sx.abs(),
+            Wall::Top => sy = sy.abs(),
+        }
+        self.ball_speed = (sx, sy);
+    }
+
+    // bring in, move and destroy enemies
+    fn update_enemies(&mut self) {
+        let spawn = match self.level.enemies {
+            Some(spawn) => spawn,
+            None => return,
+        };
+        if self.spawn_ticks > 0 {
+            self.spawn_ticks -= 1;
+        } else if self.enemies.len() < spawn.max as usize {
+            self.enemies.push(Enemy::new(spawn.behaviour, &mut self.rng));
+            self.spawn_ticks = (spawn.every * TICKS_PER_SECOND as f32) as u32;
+        }
+        let paddle = self.paddle_rect();
+        let mut killed = Vec::new();
+        for e in self.enemies.iter_mut() {
+            e.tick(self.paddle);
+        }
+        let (bx, by) = self.ball;
+        let reach = BALL_RADIUS + ENEMY_RADIUS;
+        for (ix, e) in self.enemies.iter().enumerate() {
+            let (dx, dy) = (bx - e.pos.0, by - e.pos.1);
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance <= reach && distance > 0.0 {
+                // bounce off the enemy, if the ball is not already going away
+                let (nx, ny) = (dx / distance, dy / distance);
+                let dot = self.ball_speed.0 * nx + self.ball_speed.1 * ny;
+                if dot < 0.0 {
+                    self.ball_speed.0 -= 2.0 * dot * nx;
+                    self.ball_speed.1 -= 2.0 * dot * ny;
+                }
+                self.idle_bounces = 0;
+                killed.push(ix);
+            } else if circle_touches_rect(e.pos, ENEMY_RADIUS, &paddle) {
+                killed.push(ix);
+            }
+        }
+        for ix in killed.into_iter().rev() {
+            let enemy = self.enemies.remove(ix);
+            let points = enemy_points(&self.level);
+            self.score += points;
+            self.events.push(Event::Enemy {
+                pos: enemy.pos,
+                points,
+            });
+        }
+        self.enemies.retain(|e| !e.gone());
+    }
+
+    // move the boss and its shots, a shot touching the paddle costs a ball
+    fn update_boss(&mut self) {
+        let paddle = self.paddle_rect();
+        let boss = match &mut self.boss {
+            Some(boss) if !boss.defeated() => boss,
+            _ => return,
+        };
+        boss.tick(self.paddle, &mut self.rng);
+        let shots = boss.shots.len();
+        boss.shots
+            .retain(|s| !circle_touches_rect(s.pos, SHOT_RADIUS, &paddle));
+        if boss.shots.len() < shots && !self.god {
+            self.lose_ball();
+        }
+    }
+
+    // move laser shots and capsules: shots break the first block or hit the boss,
+    // capsules give their power-up if the paddle catches them
+    fn update_projectiles(&mut self) {
+        self.laser_ticks = self.laser_ticks.saturating_sub(1);
+        self.catch_ticks = self.catch_ticks.saturating_sub(1);
+        self.fire_ticks = self.fire_ticks.saturating_sub(1);
+        let paddle = self.paddle_rect();
+        let mut broken = Vec::new();
+        let mut caught = Vec::new();
+        let level = &mut self.level;
+        let boss = &mut self.boss;
+        let mut boss_hits = Vec::new();
+        self.projectiles.retain_mut(|p| {
+            p.tick();
+            match p.kind {
+                Kind::Laser => {
+                    // the lowest block is the first one the shot meets, then the leftmost
+                    let target = level
+                        .blocks
+                        .iter()
+                        .filter(|b| b.rect.overlaps(&p.rect))
+                        .min_by(|a, b| b.rect.y.partial_cmp(&a.rect.y).unwrap_or(Ordering::Equal).then(a.i.cmp(&b.i)))
+                        .copied();
+                    if let Some(b) = target {
+                        level.blocks.remove(&b);
+                        broken.push(b);
+                        return false;
+                    }
+                    if let Some(boss) = boss {
+                        if !boss.defeated() && boss.rect.overlaps(&p.rect) {
+                            boss.health -= 1;
+                            boss_hits.push(((p.rect.x, p.rect.y), boss.defeated()));
+                            return false;
+                        }
+                    }
+                }
+                Kind::Capsule(powerup) => {
+                    if p.rect.overlaps(&paddle) {
+                        caught.push(powerup);
+                        return false;
+                    }
+                }
+            }
+            !p.gone()
+        });
+        for b in broken {
+            self.break_block(&b, 1);
+        }
+        for (pos, defeated) in boss_hits {
+            let points = boss_points(1, defeated, &self.level);
+            self.score += points;
+            self.events.push(Event::Boss { pos, points });
+        }
+        for powerup in caught {
+            self.power_up(powerup);
+        }
+        if self.level.cleared() && !self.boss_alive() {
+            self.mode = PlayMode::Won;
+        }
+    }
+
+    // score a broken block, which may drop a capsule
+    fn break_block(&mut self, block: &Block, combo: u32) {
+        let points = block_points(block, combo, &self.level);
+        self.score += points;
+        self.stats.blocks += 1;
+        self.events.push(Event::Block {
+            block: *block,
+            combo,
+            points,
+        });
+        let (laser, catch) = (self.level.laser_chance, self.level.catch_chance);
+        if laser + catch > 0.0 {
+            let roll = self.rng.gen::<f32>();
+            let powerup = if roll < laser {
+                PowerUp::Laser
+            } else if roll < laser + catch {
+                PowerUp::Catch
+            } else {
+                return;
+            };
+            let x = block.rect.x + block.rect.w / 2.0;
+            let y = block.rect.y + block.rect.h / 2.0;
+            self.projectiles.push(Projectile::capsule(powerup, x, y));
+        }
+    }
+
+    // the paddle with its rounded ends, as a rectangle
+    fn paddle_rect(&self) -> graphics::Rect {
+        let half = PADDLE_WIDTH / 2.0 + PADDLE_END;
+        graphics::Rect::new(self.paddle - half, 400.0, half * 2.0, PADDLE_HEIGHT)
+    }
+
+    // is the ball touching the paddle, a flat top between two half circles?
+    fn touches_paddle(&self) -> bool {
+        let (bx, by) = self.ball;
+        let half = PADDLE_WIDTH / 2.0;
+        if (bx - self.paddle).abs() <= half {
+            return by + BALL_RADIUS >= 400.0;
+        }
+        // rounded ends
+        let end_x = if bx < self.paddle {
+            self.paddle - half
+        } else {
+            self.paddle + half
+        };
+        let (dx, dy) = (bx - end_x, by - (400.0 + PADDLE_END));
+        let reach = BALL_RADIUS + PADDLE_END;
+        dx * dx + dy * dy <= reach * reach
+    }
+
+    // ball bounced on the paddle: the further from the center, the wider the angle
+    fn paddle_bounce(&mut self) {
+        let sx = self.ball_speed.0;
+        // back to the level speed, after bumpers
+        let speed = self.level.ball_speed;
+        let deflection = self.level.deflection;
+        let offset =
+            ((self.ball.0 - self.paddle) / (PADDLE_WIDTH / 2.0 + PADDLE_END)).clamp(-1.0, 1.0);
+        // a ball hitting the center keeps going the same way
+        let side = if offset != 0.0 {
+            offset.signum()
+        } else {
+            sx.signum()
+        };
+        let (min, max) = (deflection.min_angle, deflection.max_angle);
+        let mut angle = side * (min + (max - min) * offset.abs());
+        // a moving paddle drags the ball along
+        angle += deflection.english * (self.paddle - self.last_paddle) / PADDLE_SPEED;
+        let angle = angle.signum() * angle.abs().clamp(min, max);
+        self.ball_speed = (speed * angle.sin(), -speed * angle.cos());
+    }
+
+    // what the first fixture the ball touches does to it
+    fn touch_fixture(&self) -> Option<Touch> {
+        let speed = self.level.ball_speed;
+        self.level
+            .fixtures
+            .iter()
+            .find_map(|f| f.touch(self.ball, self.ball_speed, speed))
+    }
+
+    // calculate collision of ball with walls, blocks and paddle
+    fn collision(&mut self) {
+        let (bx, by) = self.ball;
+        // vertical wall collision
+        if bx - BALL_RADIUS <= 0.0 || bx + BALL_RADIUS >= WIDTH {
+            self.ball_speed.0 = -self.ball_speed.0;
+            self.wall_bounce(if bx - BALL_RADIUS <= 0.0 { Wall::Left } else { Wall::Right });
+        // top wall collision
+        } else if by - BALL_RADIUS <= 0.0 {
+            self.ball_speed.1 = -self.ball_speed.1;
+            self.wall_bounce(Wall::Top);
+            if self.level.speedup.top && !self.top_reached {
+                self.top_reached = true;
+                self.speed_up();
+            }
+        // ball falls below paddle, lost!
+        } else if by + BALL_RADIUS > 420.0 {
+            if self.god {
+                self.ball_speed.1 = -self.ball_speed.1.abs();
+            } else {
+                self.lose_ball();
+            }
+        // paddle collision
+        } else if self.ball_speed.1 > 0.0 && self.touches_paddle() {
+            self.events.push(Event::Paddle);
+            self.combo = 0;
+            self.idle_bounces = 0;
+            if self.catch_ticks > 0 {
+                let release = CATCH_RELEASE_SECS * TICKS_PER_SECOND;
+                self.caught = Some((self.ball.0 - self.paddle, release));
+            } else {
+                self.paddle_bounce();
+            }
+            self.paddle_hits += 1;
+            let every = self.level.speedup.paddle_hits;
+            if every > 0 && self.paddle_hits >= every {
+                self.paddle_hits = 0;
+                self.speed_up();
+            }
+        // bumpers, one-way walls and portals
+        } else if let Some(touch) = self.touch_fixture() {
+            match touch {
+                Touch::Bounce(speed) => self.ball_speed = speed,
+                Touch::Teleport(pos) => self.ball = pos,
+            }
+            self.events.push(Event::Fixture);
+        } else {
+            let (bsx, bsy) = self.ball_speed;
+            let mut bounce = Bounce::None;
+            // direction to bounce off a block that is not a rectangle
+            let mut normal = (0.0, 0.0);
+            let mut hit = Vec::new();
+            // approximate ball by rectangle
+            let ball_rect = graphics::Rect::new(
+                bx - BALL_RADIUS,
+                by - BALL_RADIUS,
+                BALL_RADIUS * 2.0,
+                BALL_RADIUS * 2.0,
+            );
+            // only blocks around the ball can be hit
+            let near = graphics::Rect::new(
+                ball_rect.x - BLOCK_WIDTH / 2.0,
+                ball_rect.y - BLOCK_HEIGHT / 2.0,
+                ball_rect.w + BLOCK_WIDTH,
+                ball_rect.h + BLOCK_HEIGHT,
+            );
+            let tested = &mut self.trace.tested;
+            tested.clear();
+            // calculate hit blocks
+            self.level.blocks.retain(|b| {
+                if !b.rect.overlaps(&near) {
+                    return true;
+                }
+                tested.push(b.rect);
+                if b.shape != Shape::Rect {
+                    return match b.normal((bx, by), BALL_RADIUS) {
+                        Some(n) => {
+                            bounce = Bounce::Slope;
+                            normal = n;
+                            hit.push(*b);
+                            false
+                        }
+                        None => true,
+                    };
+                }
+                if b.rect.overlaps(&ball_rect) {
+                    if bsy < 0.0 && by > b.rect.y + b.rect.h {
+                        bounce = Bounce::Bottom;
+                    } else if bsy > 0.0 && by < b.rect.y {
+                        bounce = Bounce::Top;
+                    } else if bsx < 0.0 && bx > b.rect.x + b.rect.w {
+                        bounce = Bounce::Right;
+                    } else {
+                        bounce = Bounce::Left;
+                    }
+                    hit.push(*b);
+                    return false;
+                }
+                true
+            });
+            // the boss, when no block was in the way
+            if bounce == Bounce::None {
+                if let Some(boss) = &mut self.boss {
+                    if let Some((side, damage)) = boss.hit(&ball_rect, (bx, by), (bsx, bsy)) {
+                        bounce = side;
+                        let points = boss_points(damage, boss.defeated(), &self.level);
+                        self.score += points;
+                        self.events.push(Event::Boss {
+                            pos: (bx, by),
+                            points,
+                        });
+                    }
+                }
+            }
+            match bounce {
+                Bounce::Bottom => {
+                    self.ball_speed.1 = -self.ball_speed.1;
+                }
+                Bounce::Top => {
+                    self.ball_speed.1 = -self.ball_speed.1;
+                }
+                Bounce::Left => {
+                    self.ball_speed.0 = -self.ball_speed.0;
+                }
+                Bounce::Right => {
+                    self.ball_speed.0 = -self.ball_speed.0;
+                }
+                // mirror the speed on the side, unless the ball is already going away from it
+                Bounce::Slope => {
+                    let dot = bsx * normal.0 + bsy * normal.1;
+                    if dot < 0.0 {
+                        self.ball_speed = (bsx - 2.0 * dot * normal.0, bsy - 2.0 * dot * normal.1);
+                    }
+                }
+                _ => {}
+            }
+            self.trace.ball_rect = ball_rect;
+            if bounce != Bounce::None {
+                self.idle_bounces = 0;
+                self.trace.bounce = bounce;
+                self.trace.hit = hit.iter().map(|b| b.rect).collect();
+            }
+            if let Some(row) = self.level.speedup.row {
+                if !self.row_reached && hit.iter().any(|b| b.j <= row) {
+                    self.row_reached = true;
+                    self.speed_up();
+                }
+            }
+            for b in hit.iter() {
+                self.combo += 1;
+                self.break_block(b, self.combo);
+            }
+            self.stats.max_combo = self.stats.max_combo.max(self.combo);
+            if self.level.cleared() && !self.boss_alive() {
+                self.mode = PlayMode::Won;
+            }
+        }
+    }
+}
+
+// How long the laser power-up lasts
+const LASER_SECS: u32 = 15;
+
+// Ticks between two laser shots
+const FIRE_TICKS: u32 = 15;
+
+// How long the catch power-up lasts
+const CATCH_SECS: u32 = 20;
+
+// How long the paddle holds the ball before letting it go by itself
+const CATCH_RELEASE_SECS: u32 = 3;
+
+// does a circle overlap a rectangle?
+fn circle_touches_rect(center: (f32, f32), radius: f32, rect: &graphics::Rect) -> bool {
+    let x = center.0.clamp(rect.x, rect.x + rect.w);
+    let y = center.1.clamp(rect.y, rect.y + rect.h);
+    let (dx, dy) = (center.0 - x, center.1 - y);
+    dx * dx + dy * dy <= radius * radius
+}
+
+// Balls the player starts the game with
+pub const LIVES: u32 = 3;
+
+// Simulation steps per second, whatever the frame rate
+pub const TICKS_PER_SECOND: u32 = 60;
+
+pub const PADDLE_WIDTH: f32 = 80.0;
+pub const PADDLE_HEIGHT: f32 = 20.0;
+
+pub const LIMIT_LEFT: f32 = PADDLE_WIDTH / 2.0 + 10.0;
+pub const LIMIT_RIGHT: f32 = WIDTH - PADDLE_WIDTH / 2.0 - 10.0;
+
+// Radius of the rounded paddle ends
+pub const PADDLE_END: f32 = 10.0;
+
+pub const PADDLE_SPEED: f32 = 8.0;
+pub const BALL_RADIUS: f32 = 10.0;
+
+// Largest deviation from vertical when launching the ball without aiming, in radians
+const LAUNCH_JITTER: f32 = 0.2;
+
+// Largest launch angle the player can aim at, in radians
+pub const MAX_AIM: f32 = 1.0;
+
+// Distance between two points of the launch path, and most points shown
+const PATH_STEP: f32 = 15.0;
+const PATH_POINTS: usize = 60;
+
+// Wall bounces without touching a block or the paddle before the ball is considered stuck
+pub const STUCK_BOUNCES: u32 = 20;
+
+// Largest angle change given to a stuck ball, in radians
+const STUCK_NUDGE: f32 = 0.3;
+
+// Smallest angle between the ball and the horizontal after a wall bounce, in radians
+const MIN_ANGLE: f32 = 0.25;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // level with the given block grid, and default settings
+    fn level(grid: &str) -> Level {
+        let def = LevelDef::parse(&format!("name Test\nblocks\n{}\n", grid)).unwrap();
+        next_level(&[def], 0)
+    }
+
+    // launched ball, then put at the given position and speed
+    fn running(level: Level, ball: (f32, f32), speed: (f32, f32)) -> Sim {
+        let mut sim = Sim::new(level, 0, LIVES, 0);
+        sim.input(Input::Launch);
+        sim.ball = ball;
+        sim.ball_speed = speed;
+        sim
+    }
+
+    // ticks until the ball breaks a block or hits the paddle, which stays under the ball;
+    // checks that the ball never leaves a wall too flat
+    fn ticks_to_hit(sim: &mut Sim) -> Option<u32> {
+        for ticks in 1..=MAX_TICKS {
+            sim.paddle = sim.ball.0.clamp(LIMIT_LEFT, LIMIT_RIGHT);
+            let bounces = sim.idle_bounces;
+            sim.tick();
+            if sim.idle_bounces > bounces {
+                let (sx, sy) = sim.ball_speed;
+                let speed = (sx * sx + sy * sy).sqrt();
+                assert!(
+                    sy.abs() >= speed * MIN_ANGLE.sin() - 1e-4,
+                    "too flat after a wall bounce: {:?}",
+                    sim.ball_speed
+                );
+            }
+            let hit = sim
+                .take_events()
+                .iter()
+                .any(|e| matches!(e, Event::Block { .. } | Event::Paddle));
+            if hit {
+                return Some(ticks);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn horizontal_ball_reaches_something() {
+        let level = level("..........#.........");
+        let speed = level.ball_speed;
+        let mut sim = running(level, (WIDTH / 2.0, 200.0), (speed, 0.0));
+        assert!(ticks_to_hit(&mut sim).is_some());
+    }
+
+    #[test]
+    fn ball_looping_above_blocks_reaches_something() {
+        let grid = format!("{}........####........", "....................\n".repeat(10));
+        let level = level(&grid);
+        let speed = level.ball_speed;
+        let mut sim = running(level, (WIDTH / 2.0, 60.0), (speed, -0.001));
+        assert!(ticks_to_hit(&mut sim).is_some());
+    }
+
+    // Longest time the ball may take to reach something, one minute of play
+    const MAX_TICKS: u32 = 60 * TICKS_PER_SECOND;
+}
@@ -0,0 +1,232 @@
+//! Level complete screen, with score breakdown
+
+use ggez::event;
+use ggez::graphics;
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use super::*;
+use crate::cli::Options;
+use crate::scoring::LevelStats;
+use crate::time_attack::TimeAttack;
+
+// A line of the summary, counting up to its value
+struct Row {
+    label: &'static str,
+    from: u32,     // value the count starts at
+    to: u32,       // final value
+    as_time: bool, // display value as minutes:seconds
+}
+
+impl Row {
+    fn new(label: &'static str, to: u32) -> Self {
+        Self {
+            label,
+            from: 0,
+            to,
+            as_time: false,
+        }
+    }
+
+    // value shown given the row progress between 0 and 1
+    fn value(&self, progress: f32) -> String {
+        let progress = progress.clamp(0.0, 1.0);
+        let v = self.from + ((self.to - self.from) as f32 * progress) as u32;
+        if self.as_time {
+            format!("{}:{:02}", v / 60, v % 60)
+        } else {
+            format!("{}", v)
+        }
+    }
+}
+
+// Summary shown once all blocks of a level are broken
+pub struct LevelCompleteState {
+    assets: Rc<Assets>,
+    options: Rc<Options>,
+    title_text: graphics::Text,
+    continue_text: graphics::Text,
+    rows: Vec<Row>,
+    next: u32,                  // level to play next
+    score: u32,                 // score including bonuses
+    lives: u32,                 // lives left
+    attack: Option<TimeAttack>, // time attack run, if playing one
+    record: bool,               // was the time attack run finished faster than ever?
+    start: Instant,
+}
+
+impl LevelCompleteState {
+    pub fn new(
+        assets: Rc<Assets>,
+        options: Rc<Options>,
+        stats: LevelStats,
+        score: u32,
+        lives: u32,
+        attack: Option<TimeAttack>,
+    ) -> Self {
+        let title = if stats.skipped > 0 {
+            format!("Level {} warped", stats.index + 1)
+        } else {
+            format!("Level {} complete", stats.index + 1)
+        };
+        let title_text = graphics::Text::new((title, assets.font, 36.0));
+        let continue_text = graphics::Text::new(("Press <SPACE> to continue", assets.font, 25.0));
+        let time_bonus = stats.time_bonus();
+        let life_bonus = stats.no_life_lost_bonus();
+        let warp_bonus = stats.warp_bonus();
+        let total = score + time_bonus + life_bonus + warp_bonus;
+        let mut rows = vec![
+            Row::new("Blocks broken", stats.blocks),
+            Row {
+                as_time: true,
+                ..Row::new("Time", stats.time.as_secs() as u32)
+            },
+            Row::new("Max combo", stats.max_combo),
+            Row::new("Time bonus", time_bonus),
+            Row::new("No life lost bonus", life_bonus),
+        ];
+        if warp_bonus > 0 {
+            rows.push(Row::new("Warp bonus", warp_bonus));
+        }
+        rows.push(Row {
+            from: score,
+            ..Row::new("Score", total)
+        });
+        let next = stats.index + 1 + stats.skipped;
+        let mut record = false;
+        if let Some(attack) = &attack {
+            let seconds = |ticks: u32| ticks / TICKS_PER_SECOND;
+            rows.push(Row {
+                as_time: true,
+                ..Row::new("Level time", seconds(attack.last))
+            });
+            rows.push(Row {
+                as_time: true,
+                ..Row::new("Total time", seconds(attack.ticks))
+            });
+            if let Some(best) = attack.best_at(stats.index) {
+                rows.push(Row {
+                    as_time: true,
+                    ..Row::new("Best run", seconds(best))
+                });
+            }
+            // the run is over: keep it if it is the fastest
+            if attack.finished(next) && attack.is_record() {
+                record = true;
+                if let Err(e) = attack.save() {
+                    println!("Cannot save best times: {}", e);
+                }
+            }
+        }
+        Self {
+            assets,
+            options,
+            title_text,
+            continue_text,
+            rows,
+            next,
+            score: total,
+            lives,
+            attack,
+            record,
+            start: Instant::now(),
+        }
+    }
+
+    // how far the counting has gone, in rows: row i counts while this is between i and i+1
+    fn counted(&self) -> f32 {
+        let elapsed = Instant::now() - self.start;
+        elapsed.as_millis() as f32 / MILLIS_PER_ROW as f32
+    }
+
+    fn done(&self) -> bool {
+        self.counted() >= self.rows.len() as f32
+    }
+}
+
+impl event::EventHandler for LevelCompleteState {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    // space while counting skips to the final values
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: event::KeyCode,
+        _keymod: event::KeyMods,
+        _repeat: bool,
+    ) {
+        if keycode == event::KeyCode::Space {
+            let all = Duration::from_millis(MILLIS_PER_ROW * self.rows.len() as u64);
+            if let Some(start) = Instant::now().checked_sub(all) {
+                self.start = start;
+            }
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, LIGHTGRAY);
+        let (w, _h) = self.title_text.dimensions(ctx);
+        let dest_point = na::Point2::new(WIDTH / 2.0 - (w as f32 / 2.0), 60.0);
+        graphics::draw(ctx, &self.title_text, (dest_point, RED))?;
+
+        // rows only appear once they start counting
+        let counted = self.counted();
+        for (i, row) in self.rows.iter().enumerate() {
+            if counted < i as f32 {
+                break;
+            }
+            let y = 130.0 + i as f32 * 35.0;
+            let label = graphics::Text::new((row.label, self.assets.font, 22.0));
+            graphics::draw(ctx, &label, (na::Point2::new(180.0, y), DARKGRAY))?;
+            let value = graphics::Text::new((row.value(counted - i as f32), self.assets.font, 22.0));
+            let (w, _h) = value.dimensions(ctx);
+            graphics::draw(
+                ctx,
+                &value,
+                (na::Point2::new(620.0 - w as f32, y), graphics::BLACK),
+            )?;
+        }
+
+        if self.done() {
+            let (w, _h) = self.continue_text.dimensions(ctx);
+            let dest_point = na::Point2::new(WIDTH / 2.0 - (w as f32 / 2.0), 390.0);
+            graphics::draw(ctx, &self.continue_text, (dest_point, graphics::BLACK))?;
+        }
+        Ok(())
+    }
+}
+
+impl InnerState for LevelCompleteState {
+    fn transition(&self, keycode: event::KeyCode) -> Transition {
+        if keycode == event::KeyCode::Space && self.done() {
+            if let Some(attack) = &self.attack {
+                if attack.finished(self.next) {
+                    let message = if self.record { "NEW RECORD" } else { "FINISHED" };
+                    let end = EndState::new(message, self.assets.clone(), self.options.clone());
+                    return Transition::Animate(Box::new(end), Effect::Fade);
+                }
+            }
+            let intro = IntroState::new(
+                self.assets.clone(),
+                self.options.clone(),
+                self.next,
+                self.score,
+                self.lives,
+                self.attack.clone(),
+            );
+            return Transition::Animate(Box::new(intro), Effect::Slide);
+        }
+        Transition::None
+    }
+
+    fn music(&self) -> Option<Music> {
+        Some(Music::Complete)
+    }
+}
+
+// Time to count up a single row
+const MILLIS_PER_ROW: u64 = 500;
@@ -0,0 +1,94 @@
+//! Color themes for the play screen
+
+use ggez::graphics;
+
+use super::*;
+use crate::level::{BlockKind, ParseError};
+
+// Colors used to draw a level
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    pub background: graphics::Color,   // screen background
+    pub light: graphics::Color,        // light blocks
+    pub dark: graphics::Color,         // dark blocks
+    pub stroke: graphics::Color,       // block outline
+    pub paddle: graphics::Color,       // paddle ends
+    pub paddle_inner: graphics::Color, // paddle middle
+    pub ball: graphics::Color,         // ball
+    pub outline: graphics::Color,      // paddle and ball outline
+    pub text: graphics::Color,         // help, score and lives
+    pub enemy: graphics::Color,        // enemies
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            background: LIGHTGRAY,
+            light: GRAY,
+            dark: DARKGRAY,
+            stroke: graphics::BLACK,
+            paddle: DARKGRAY,
+            paddle_inner: LIGHTGRAY,
+            ball: RED,
+            outline: DARKGRAY,
+            text: DARKGRAY,
+            enemy: DARKGRAY,
+        }
+    }
+}
+
+impl Theme {
+    // fill color of a block
+    pub fn fill(&self, kind: BlockKind) -> graphics::Color {
+        match kind {
+            BlockKind::Light => self.light,
+            BlockKind::Dark => self.dark,
+            BlockKind::Hidden => self.background,
+        }
+    }
+
+    // Parse a theme file
+    // Each line is a color name followed by red, green and blue values between 0 and 255.
+    // Colors not given keep their default value. Lines starting with '#' are comments.
+    pub fn parse(text: &str) -> Result<Theme, ParseError> {
+        let mut theme = Theme::default();
+        for (ix, line) in text.lines().enumerate() {
+            let line_no = ix + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let key = words.next().unwrap_or_default();
+            let args: Vec<&str> = words.collect();
+            let color = parse_color(line_no, &args)?;
+            match key {
+                "background" => theme.background = color,
+                "light" => theme.light = color,
+                "dark" => theme.dark = color,
+                "stroke" => theme.stroke = color,
+                "paddle" => theme.paddle = color,
+                "paddle_inner" => theme.paddle_inner = color,
+                "ball" => theme.ball = color,
+                "outline" => theme.outline = color,
+                "text" => theme.text = color,
+                "enemy" => theme.enemy = color,
+                _ => return Err(ParseError::new(line_no, format!("unknown color '{}'", key))),
+            }
+        }
+        Ok(theme)
+    }
+}
+
+// parse red, green and blue components
+fn parse_color(line_no: usize, args: &[&str]) -> Result<graphics::Color, ParseError> {
+    let rgb: Vec<u8> = args
+        .iter()
+        .map(|a| a.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| ParseError::new(line_no, "color components must be between 0 and 255"))?;
+    match rgb.as_slice() {
+        [r, g, b] => Ok(graphics::Color::from_rgb(*r, *g, *b)),
+        _ => Err(ParseError::new(line_no, "expected red, green and blue values")),
+    }
+}
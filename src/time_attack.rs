@@ -0,0 +1,114 @@
+//! Time attack: clear every level once, as fast as possible, against the best run so far
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::sim::TICKS_PER_SECOND;
+
+// A time attack run
+#[derive(Clone, Debug)]
+pub struct TimeAttack {
+    pub levels: u32,              // levels in a run
+    pub ticks: u32,               // ticks played in finished levels
+    pub last: u32,                // ticks played in the last finished level
+    pub splits: Vec<Option<u32>>, // total ticks at the end of each level, None if not played
+    pub best: Vec<Option<u32>>,   // the same for the best run, empty if there is none yet
+    path: PathBuf,                // file the best run is saved to
+}
+
+impl TimeAttack {
+    // new run over the given number of levels, comparing with the best run saved in the file
+    pub fn new(levels: u32, path: &Path) -> Self {
+        let best = if path.exists() {
+            match load(path, levels) {
+                Ok(best) => best,
+                Err(e) => {
+                    println!("Cannot read best times: {}", e);
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        Self {
+            levels,
+            ticks: 0,
+            last: 0,
+            splits: vec![None; levels as usize],
+            best,
+            path: path.to_path_buf(),
+        }
+    }
+
+    // record the time taken by a finished level
+    pub fn finish_level(&mut self, index: u32, ticks: u32) {
+        self.ticks += ticks;
+        self.last = ticks;
+        if let Some(split) = self.splits.get_mut(index as usize) {
+            *split = Some(self.ticks);
+        }
+    }
+
+    // is the run over once the given level is reached?
+    pub fn finished(&self, next: u32) -> bool {
+        next >= self.levels
+    }
+
+    // total ticks of the best run at the end of the given level
+    pub fn best_at(&self, index: u32) -> Option<u32> {
+        self.best.get(index as usize).copied().flatten()
+    }
+
+    // total ticks of the best run
+    pub fn best_total(&self) -> Option<u32> {
+        self.best.iter().rev().find_map(|s| *s)
+    }
+
+    // is this run faster than the best one?
+    pub fn is_record(&self) -> bool {
+        match self.best_total() {
+            Some(best) => self.ticks < best,
+            None => true,
+        }
+    }
+
+    // save the splits as the best run: one line per level with the total ticks, '-' if skipped
+    pub fn save(&self) -> Result<(), String> {
+        let text: String = self
+            .splits
+            .iter()
+            .map(|s| match s {
+                Some(ticks) => format!("{}\n", ticks),
+                None => "-\n".to_owned(),
+            })
+            .collect();
+        fs::write(&self.path, text).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+// read the best run, ignoring it if it was for a different number of levels
+fn load(path: &Path, levels: u32) -> Result<Vec<Option<u32>>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut best = Vec::new();
+    for (ix, line) in text.lines().enumerate() {
+        let split = match line.trim() {
+            "-" => None,
+            ticks => Some(
+                ticks
+                    .parse()
+                    .map_err(|_| format!("{}: line {}: invalid time '{}'", path.display(), ix + 1, ticks))?,
+            ),
+        };
+        best.push(split);
+    }
+    if best.len() != levels as usize {
+        return Ok(Vec::new());
+    }
+    Ok(best)
+}
+
+// ticks as minutes, seconds and hundredths
+pub fn format_ticks(ticks: u32) -> String {
+    let hundredths = ticks * 100 / TICKS_PER_SECOND;
+    format!("{}:{:02}.{:02}", hundredths / 6000, hundredths / 100 % 60, hundredths % 100)
+}
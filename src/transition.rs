@@ -0,0 +1,94 @@
+//! Animated transitions between states
+
+use ggez::graphics;
+use ggez::nalgebra as na;
+use ggez::{Context, GameResult};
+use std::time::{Duration, Instant};
+
+use super::*;
+
+// Visual effect used when a state replaces another
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Effect {
+    Fade,  // fade to black, then from black to the new state
+    Slide, // new state pushes the old one out to the left
+    Wipe,  // new state is revealed from left to right
+}
+
+// Animation running between the outgoing and the incoming state
+pub struct Animation {
+    from: Box<dyn InnerState>, // outgoing state
+    effect: Effect,            // effect to use
+    start: Instant,            // when the animation started
+}
+
+impl Animation {
+    pub fn new(from: Box<dyn InnerState>, effect: Effect) -> Self {
+        Self {
+            from,
+            effect,
+            start: Instant::now(),
+        }
+    }
+
+    // progress between 0 (start) and 1 (done)
+    fn progress(&self) -> f32 {
+        let elapsed = Instant::now() - self.start;
+        (elapsed.as_millis() as f32 / MILLIS_PER_TRANSITION as f32).min(1.0)
+    }
+
+    pub fn finished(&self) -> bool {
+        Instant::now() - self.start >= Duration::from_millis(MILLIS_PER_TRANSITION)
+    }
+
+    // draw both states off screen, then combine them on screen according to the effect
+    pub fn draw(
+        &mut self,
+        ctx: &mut Context,
+        to: &mut dyn InnerState,
+        canvases: &(graphics::Canvas, graphics::Canvas),
+    ) -> GameResult {
+        let (from_canvas, to_canvas) = canvases;
+        graphics::set_canvas(ctx, Some(from_canvas));
+        self.from.draw(ctx)?;
+        graphics::set_canvas(ctx, Some(to_canvas));
+        to.draw(ctx)?;
+        graphics::set_canvas(ctx, None);
+
+        let t = self.progress();
+        match self.effect {
+            Effect::Fade => {
+                // first half darkens the old state, second half lightens the new one
+                let (canvas, alpha) = if t < 0.5 {
+                    (from_canvas, t * 2.0)
+                } else {
+                    (to_canvas, (1.0 - t) * 2.0)
+                };
+                graphics::draw(ctx, canvas, graphics::DrawParam::new())?;
+                let mb = &mut graphics::MeshBuilder::new();
+                mb.rectangle(
+                    graphics::DrawMode::Fill(graphics::FillOptions::default()),
+                    graphics::Rect::new(0.0, 0.0, WIDTH, HEIGHT),
+                    graphics::Color::new(0.0, 0.0, 0.0, alpha),
+                );
+                let m = &mb.build(ctx)?;
+                graphics::draw(ctx, m, graphics::DrawParam::new())?;
+            }
+            Effect::Slide => {
+                let offset = t * WIDTH;
+                graphics::draw(ctx, from_canvas, (na::Point2::new(-offset, 0.0),))?;
+                graphics::draw(ctx, to_canvas, (na::Point2::new(WIDTH - offset, 0.0),))?;
+            }
+            Effect::Wipe => {
+                graphics::draw(ctx, from_canvas, graphics::DrawParam::new())?;
+                // source rectangle is in texture coordinates
+                let src = graphics::Rect::new(0.0, 0.0, t, 1.0);
+                graphics::draw(ctx, to_canvas, graphics::DrawParam::new().src(src))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Duration of a transition animation
+const MILLIS_PER_TRANSITION: u64 = 600;